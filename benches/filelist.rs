@@ -0,0 +1,81 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use rnr_buf::{Config, FileList, MemoryFileSystem};
+
+/// Build a listing of `size` synthetic files spread over directories of 1000 entries.
+fn synthetic_list(size: usize) -> FileList {
+    let mut list = FileList::new();
+    for i in 0..size {
+        list.insert(format!("dir_{}/file_{}.txt", i / 1000, i).into(), 0);
+    }
+    list.enumerate();
+
+    list
+}
+
+/// The buffer the user would save after renaming every single entry.
+fn renamed_buffer(original: &FileList) -> String {
+    let mut raw = String::with_capacity(original.raw.len() * 2);
+    for line in original.raw.lines() {
+        raw += &line.replace("file_", "renamed_");
+        raw.push('\n');
+    }
+
+    raw
+}
+
+/// Tree holding every file of the listing.
+fn synthetic_tree(original: &FileList) -> MemoryFileSystem {
+    MemoryFileSystem::with_files(original.list.iter().map(|path| &path.source))
+}
+
+/// Plan the edited buffer against the tree and run the renames, the way the
+/// CLI does once the editor is closed.
+fn rename_all(fs: &MemoryFileSystem, original: &FileList, raw: &str, config: &Config) -> usize {
+    let modified = rnr_buf::parse_buffer(raw).expect("Failed to parse buffer");
+    let activities =
+        rnr_buf::plan_with(fs, original, &modified, config).expect("Failed to plan renames");
+    rnr_buf::execute_with(fs, &activities, config).expect("Failed to run renames");
+
+    activities.len()
+}
+
+fn bench_rename(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rename");
+    group.sample_size(10);
+
+    let config = Config {
+        yes: true,
+        quiet: true,
+        ..Config::new()
+    };
+    for size in [100_000, 1_000_000] {
+        let original = synthetic_list(size);
+        let raw = renamed_buffer(&original);
+
+        group.bench_with_input(BenchmarkId::new("enumerate", size), &size, |b, &size| {
+            b.iter(|| synthetic_list(black_box(size)))
+        });
+        group.bench_with_input(BenchmarkId::new("plan", size), &raw, |b, raw| {
+            let fs = synthetic_tree(&original);
+            b.iter(|| {
+                let modified =
+                    rnr_buf::parse_buffer(black_box(raw)).expect("Failed to parse buffer");
+                rnr_buf::plan_with(&fs, &original, &modified, &config)
+                    .expect("Failed to plan renames")
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("plan_execute", size), &raw, |b, raw| {
+            b.iter_batched(
+                || synthetic_tree(&original),
+                |fs| rename_all(&fs, &original, black_box(raw), &config),
+                BatchSize::PerIteration,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_rename);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+;;; rnr-buf-mode.el --- Major mode for rnr-buf rename buffers -*- lexical-binding: t -*-
+
+;;; Commentary:
+
+;; Highlights comments, directories, the fields identifying each entry
+;; (columns and the left column of two-column lines) and the error
+;; annotations written by rnr-buf in the buffers it opens (files ending in
+;; `.rnr-buf').
+
+;;; Code:
+
+(defvar rnr-buf-font-lock-keywords
+  '(("^# \\[rnr-buf error\\]:.*$" . font-lock-warning-face)
+    ("^#.*$" . font-lock-comment-face)
+    ;; fields before the last tab: the columns and the listed path
+    ("^[^#\n].*\t" . font-lock-constant-face)
+    ("^[^#\t\n][^\t\n]*[/\\\\]" . font-lock-function-name-face)
+    ("\t\\([^\t\n]*[/\\\\]\\)" 1 font-lock-function-name-face))
+  "Highlighting for `rnr-buf-mode'.")
+
+;;;###autoload
+(define-derived-mode rnr-buf-mode text-mode "rnr-buf"
+  "Major mode for editing rnr-buf rename buffers."
+  (setq-local comment-start "# ")
+  (setq-local font-lock-defaults '(rnr-buf-font-lock-keywords t)))
+
+;;;###autoload
+(add-to-list 'auto-mode-alist '("\\.rnr-buf\\'" . rnr-buf-mode))
+
+(provide 'rnr-buf-mode)
+
+;;; rnr-buf-mode.el ends here
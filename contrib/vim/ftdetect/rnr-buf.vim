@@ -0,0 +1 @@
+autocmd BufRead,BufNewFile *.rnr-buf setfiletype rnr-buf
@@ -0,0 +1,27 @@
+" Vim syntax file
+" Language: rnr-buf rename buffer
+
+if exists("b:current_syntax")
+  finish
+endif
+
+" Everything up to the last separator of a path is its directory
+syntax match rnrBufDirectory "^[^#\t][^\t]*[/\\]"
+
+" Fields before the last tab, the columns and the left column of a two-column
+" line, identify the entry the line stands for
+syntax match rnrBufId "^[^#].*\t" nextgroup=rnrBufTargetDirectory
+syntax match rnrBufTargetDirectory "[^\t]*[/\\]" contained
+
+syntax match rnrBufComment "^#.*$" contains=rnrBufModeline,rnrBufError
+syntax match rnrBufModeline "-\*-.*-\*-\|vim:.*$" contained
+syntax match rnrBufError "^# \[rnr-buf error\]:.*$" contained
+
+highlight default link rnrBufDirectory Directory
+highlight default link rnrBufTargetDirectory Directory
+highlight default link rnrBufId Identifier
+highlight default link rnrBufComment Comment
+highlight default link rnrBufModeline PreProc
+highlight default link rnrBufError Error
+
+let b:current_syntax = "rnr-buf"
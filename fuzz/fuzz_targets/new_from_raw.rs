@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rnr_buf::FileList;
+
+fuzz_target!(|raw: &str| {
+    let list = match FileList::new_from_raw(raw.to_owned()) {
+        Ok(list) => list,
+        Err(_) => return,
+    };
+
+    // every entry can be found back by its position and its path
+    for entry in &list.list {
+        let by_index = list.get_by_index(entry.position).unwrap();
+        assert_eq!(entry.source, by_index.source);
+
+        let by_file = list.get_by_file(&entry.source).unwrap();
+        assert_eq!(entry.position, by_file.position);
+    }
+});
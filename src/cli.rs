@@ -1,4 +1,8 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use rnr_buf::{Column, ConfigValues, ConflictStrategy, Transform};
 
 #[derive(Parser)]
 #[command(
@@ -10,38 +14,309 @@ use clap::Parser;
     args_override_self = true
 )]
 pub struct Opts {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     // File names
     pub paths: Vec<String>,
 
     /// By default it picks the system env EDITOR, otherwise the one given
-    #[arg(short, long)]
+    #[arg(global = true, short, long)]
     pub editor: Option<String>,
 
-    #[arg(short = 'R', long)]
+    #[arg(global = true, short = 'R', long, overrides_with = "no_recursive")]
     pub recursive: bool,
 
+    /// Don't list directories recursively.
+    #[arg(global = true, long, overrides_with = "recursive")]
+    pub no_recursive: bool,
+
     /// Whether to use the absolute path or not
-    #[arg(short = 'a', long)]
+    #[arg(global = true, short = 'a', long, overrides_with = "no_absolute")]
     pub absolute: bool,
 
+    /// Use paths relative to the current directory.
+    #[arg(global = true, long, overrides_with = "absolute")]
+    pub no_absolute: bool,
+
     /// Whether to not fail in case of finding a target with the same filename,
-    /// it automatically adjusts it.
-    #[arg(long)]
+    /// it automatically adjusts it. Same as `--on-conflict rename`.
+    #[arg(global = true, long)]
     pub automatic_rename: bool,
 
+    /// What to do when a target with the same filename exists.
+    #[arg(global = true, long, value_enum, value_name = "STRATEGY")]
+    pub on_conflict: Option<ConflictStrategy>,
+
     /// Whether to ignore hidden files and directories (or not).
-    #[arg(long)]
+    #[arg(global = true, long, overrides_with = "no_ignore_hidden")]
     pub ignore_hidden: bool,
 
+    /// List hidden files and directories.
+    #[arg(global = true, long, overrides_with = "ignore_hidden")]
+    pub no_ignore_hidden: bool,
+
     /// Whether to automatically create dirs or not.
-    #[arg(long)]
+    #[arg(global = true, long, overrides_with = "no_mkdir")]
     pub mkdir: bool,
 
+    /// Don't create missing dirs.
+    #[arg(global = true, long, overrides_with = "mkdir")]
+    pub no_mkdir: bool,
+
     /// Confirm all changes.
-    #[arg(short = 'y', long)]
+    #[arg(global = true, short = 'y', long, overrides_with = "no_yes")]
     pub yes: bool,
 
+    /// Ask for confirmation, even when `yes` is set in a config file.
+    #[arg(global = true, long, overrides_with = "yes")]
+    pub no_yes: bool,
+
     /// Whether to have terminal output or not. It will fail on error.
-    #[arg(short = 'q', long)]
+    #[arg(global = true, short = 'q', long, overrides_with = "no_quiet")]
     pub quiet: bool,
+
+    /// Print the terminal output.
+    #[arg(global = true, long, overrides_with = "quiet")]
+    pub no_quiet: bool,
+
+    /// Write a Vim/Emacs modeline at the top of the buffer.
+    #[arg(global = true, long, overrides_with = "no_modeline")]
+    pub modeline: bool,
+
+    /// Don't write a modeline at the top of the buffer.
+    #[arg(global = true, long, overrides_with = "modeline")]
+    pub no_modeline: bool,
+
+    /// Treat target directories as case-insensitive instead of probing them.
+    #[arg(global = true, long, overrides_with = "no_case_insensitive")]
+    pub case_insensitive: bool,
+
+    /// Probe each target directory for case-insensitivity.
+    #[arg(global = true, long, overrides_with = "case_insensitive")]
+    pub no_case_insensitive: bool,
+
+    /// Template for `--on-conflict rename`, with `{stem}`, `{n}` (or `{n:03}`)
+    /// and `{ext}` placeholders. Defaults to `{stem}_{n}{ext}`.
+    #[arg(global = true, long, value_name = "TEMPLATE")]
+    pub autoname_template: Option<String>,
+
+    /// Skip entries changed on disk since they were listed instead of aborting.
+    #[arg(global = true, long, overrides_with = "no_skip_changed")]
+    pub skip_changed: bool,
+
+    /// Abort on entries changed on disk since they were listed.
+    #[arg(global = true, long, overrides_with = "skip_changed")]
+    pub no_skip_changed: bool,
+
+    /// Rename tracked files with `git mv`, staging the renames. Detected when
+    /// running inside a work tree.
+    #[arg(global = true, long, overrides_with = "no_git")]
+    pub git: bool,
+
+    /// Never rename through git, even inside a work tree.
+    #[arg(global = true, long, overrides_with = "git")]
+    pub no_git: bool,
+
+    /// Rename tracked files even when they have unstaged changes.
+    #[arg(global = true, short = 'f', long, overrides_with = "no_force")]
+    pub force: bool,
+
+    /// Refuse to rename tracked files with unstaged changes.
+    #[arg(global = true, long, overrides_with = "force")]
+    pub no_force: bool,
+
+    /// Rewrite the symlinks pointing at renamed files, keeping them relative
+    /// or absolute.
+    #[arg(global = true, long, overrides_with = "no_fix_symlinks")]
+    pub fix_symlinks: bool,
+
+    /// Leave the symlinks pointing at renamed files as they are.
+    #[arg(global = true, long, overrides_with = "fix_symlinks")]
+    pub no_fix_symlinks: bool,
+
+    /// Where to look for symlinks with `--fix-symlinks`. Defaults to the
+    /// current directory.
+    #[arg(global = true, long, value_name = "DIR")]
+    pub symlink_root: Option<PathBuf>,
+
+    /// Rewrite the paths and names of renamed files found in the text files
+    /// matching the glob, e.g. `'**/*.md'`. Can be given more than once.
+    #[arg(global = true, long, value_name = "GLOB")]
+    pub update_references: Vec<String>,
+
+    /// Where to look for the files of `--update-references`. Defaults to the
+    /// current directory.
+    #[arg(global = true, long, value_name = "DIR")]
+    pub reference_root: Option<PathBuf>,
+
+    /// Extra columns to edit before each path, among `mode`, `owner` and
+    /// `mtime`, e.g. `--columns mode,owner`.
+    #[arg(
+        global = true,
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "COLUMNS"
+    )]
+    pub columns: Vec<Column>,
+
+    /// Allow deletions: emptying a line moves its file or dir to the trash.
+    #[arg(global = true, long, overrides_with = "no_delete")]
+    pub delete: bool,
+
+    /// Refuse deletions, emptied lines are an error.
+    #[arg(global = true, long, overrides_with = "delete")]
+    pub no_delete: bool,
+
+    /// List each path twice, `source<TAB>destination`, and edit only the
+    /// right column so lines can be reordered freely.
+    #[arg(global = true, long, overrides_with = "no_two_column")]
+    pub two_column: bool,
+
+    /// List each path once.
+    #[arg(global = true, long, overrides_with = "two_column")]
+    pub no_two_column: bool,
+
+    /// Rewrite the file names of the buffer before editing it, among `lower`,
+    /// `upper`, `title`, `snake`, `kebab`, `ascii`, `slug` and `collapse`.
+    /// Can be given more than once, applied in order.
+    #[arg(global = true, long, value_enum, value_name = "TRANSFORM")]
+    pub transform: Vec<Transform>,
+
+    /// Skip the editor and run the buffer as listed, e.g. with `--transform`.
+    #[arg(global = true, long, overrides_with = "edit")]
+    pub no_editor: bool,
+
+    /// Open the editor, even when `no_editor` is set in a config file.
+    #[arg(global = true, long, overrides_with = "no_editor")]
+    pub edit: bool,
+
+    /// Name every listed file with a template before editing, e.g.
+    /// `trip-{n:03}{ext}`. Placeholders: `{n}` (the counter, `{n:03}` zero
+    /// padded), `{stem}`, `{ext}`, `{parent}` (name of the parent dir),
+    /// `{mtime:%Y-%m-%d}`, `{size}`, `{sha256:8}` (first hex digits),
+    /// `{exif:<tag>}` (e.g. `{exif:DateTimeOriginal:%Y}`), `{image:width}`,
+    /// `{image:height}` and `{id3:<tag>}` (e.g. `{id3:track:02}`).
+    #[arg(global = true, long, value_name = "TEMPLATE")]
+    pub template: Option<String>,
+
+    /// First value of the `{n}` counter of `--template`. Defaults to 1.
+    #[arg(global = true, long, value_name = "N")]
+    pub start: Option<u64>,
+
+    /// Increment of the `{n}` counter of `--template`. Defaults to 1.
+    #[arg(global = true, long, value_name = "N")]
+    pub step: Option<u64>,
+
+    /// Start the `{n}` counter of `--template` over in each directory.
+    #[arg(global = true, long, overrides_with = "no_per_dir")]
+    pub per_dir: bool,
+
+    /// Keep one `{n}` counter of `--template` across directories.
+    #[arg(global = true, long, overrides_with = "per_dir")]
+    pub no_per_dir: bool,
+
+    /// Start from a buffer saved by a failed run instead of a fresh listing.
+    #[arg(global = true, long, value_name = "FILE")]
+    pub resume: Option<PathBuf>,
+
+    /// Named profile from the config files to apply on top of their defaults.
+    #[arg(global = true, short = 'p', long)]
+    pub profile: Option<String>,
+
+    /// Ignore the user and project config files.
+    #[arg(global = true, long)]
+    pub no_config: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Edit the buffer and write the operations to a file instead of running
+    /// them.
+    Plan {
+        /// Where to write the plan, as JSON.
+        #[arg(short, long, value_name = "FILE")]
+        out: PathBuf,
+
+        // File names
+        paths: Vec<String>,
+    },
+    /// Check a plan written by `plan` against the current tree and run it.
+    Apply {
+        /// Plan written by `rnr-buf plan`.
+        plan: PathBuf,
+    },
+}
+
+impl Opts {
+    /// Flags set on the command line, either way with their `--no-*`
+    /// counterparts, unset ones are left as `None`.
+    pub fn config_values(&self) -> ConfigValues {
+        ConfigValues {
+            absolute: flag(self.absolute, self.no_absolute),
+            editor: self.editor.clone(),
+            recursive: flag(self.recursive, self.no_recursive),
+            on_conflict: self
+                .on_conflict
+                .or(self.automatic_rename.then_some(ConflictStrategy::Rename)),
+            ignore_hidden: flag(self.ignore_hidden, self.no_ignore_hidden),
+            mkdir: flag(self.mkdir, self.no_mkdir),
+            yes: flag(self.yes, self.no_yes),
+            quiet: flag(self.quiet, self.no_quiet),
+            modeline: flag(self.modeline, self.no_modeline),
+            case_insensitive: flag(self.case_insensitive, self.no_case_insensitive),
+            autoname_template: self.autoname_template.clone(),
+            skip_changed: flag(self.skip_changed, self.no_skip_changed),
+            git: flag(self.git, self.no_git),
+            force: flag(self.force, self.no_force),
+            fix_symlinks: flag(self.fix_symlinks, self.no_fix_symlinks),
+            symlink_root: self.symlink_root.clone(),
+            update_references: (!self.update_references.is_empty())
+                .then(|| self.update_references.clone()),
+            reference_root: self.reference_root.clone(),
+            columns: (!self.columns.is_empty()).then(|| self.columns.clone()),
+            delete: flag(self.delete, self.no_delete),
+            two_column: flag(self.two_column, self.no_two_column),
+            transform: (!self.transform.is_empty()).then(|| self.transform.clone()),
+            no_editor: flag(self.no_editor, self.edit),
+            template: self.template.clone(),
+            start: self.start,
+            step: self.step,
+            per_dir: flag(self.per_dir, self.no_per_dir),
+        }
+    }
+}
+
+/// A flag and its `--no-*` counterpart, `None` when neither was given.
+fn flag(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::Opts;
+
+    #[test]
+    fn test_config_values() {
+        let opts = Opts::parse_from([
+            "rnr-buf",
+            "--no-recursive",
+            "--mkdir",
+            "--automatic-rename",
+            ".",
+        ]);
+        let values = opts.config_values();
+
+        assert_eq!(Some(false), values.recursive);
+        assert_eq!(Some(true), values.mkdir);
+        assert_eq!(Some(rnr_buf::ConflictStrategy::Rename), values.on_conflict);
+        assert_eq!(None, values.yes);
+    }
 }
@@ -0,0 +1,306 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Separates the columns from each other and from the path.
+pub const SEPARATOR: char = '\t';
+
+/// Written for values that could not be read, and left unchanged.
+const UNKNOWN: &str = "-";
+
+const MTIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Extra column of the buffer, written before the path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    /// Permission bits in octal, e.g. `0644`.
+    Mode,
+    /// `user:group`, names or ids.
+    Owner,
+    /// Modification time, local, e.g. `2024-01-31T12:00:00`.
+    Mtime,
+}
+
+/// Owner of a file, the names are kept as written.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Owner {
+    pub user: String,
+    pub group: String,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.user, self.group)
+    }
+}
+
+/// Values of the columns of a line, `None` when unknown or not shown.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Attributes {
+    pub mode: Option<u32>,
+    pub owner: Option<Owner>,
+    /// Seconds since the epoch, the precision of the buffer.
+    pub mtime: Option<i64>,
+}
+
+/// A change of the attributes of a file, run after its rename.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributeChange {
+    Mode(u32),
+    Owner(Owner),
+    Mtime(i64),
+}
+
+impl fmt::Display for AttributeChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeChange::Mode(mode) => write!(f, "chmod {}", format_mode(*mode)),
+            AttributeChange::Owner(owner) => write!(f, "chown {}", owner),
+            AttributeChange::Mtime(mtime) => write!(f, "touch {}", format_mtime(*mtime)),
+        }
+    }
+}
+
+impl AttributeChange {
+    pub fn mtime(seconds: i64) -> SystemTime {
+        if seconds >= 0 {
+            UNIX_EPOCH + Duration::from_secs(seconds as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+        }
+    }
+}
+
+fn format_mode(mode: u32) -> String {
+    format!("{:04o}", mode)
+}
+
+fn format_mtime(seconds: i64) -> String {
+    match Local.timestamp_opt(seconds, 0).single() {
+        Some(time) => time.format(MTIME_FORMAT).to_string(),
+        None => UNKNOWN.to_owned(),
+    }
+}
+
+impl Attributes {
+    /// Attributes of a path, without following symlinks.
+    pub fn of(path: &Path) -> Self {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Self::default(),
+        };
+
+        let mtime = metadata
+            .modified()
+            .ok()
+            .map(|mtime| match mtime.duration_since(UNIX_EPOCH) {
+                Ok(after) => after.as_secs() as i64,
+                Err(before) => -(before.duration().as_secs() as i64),
+            });
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            Self {
+                mode: Some(metadata.mode() & 0o7777),
+                owner: Some(owner_of(metadata.uid(), metadata.gid())),
+                mtime,
+            }
+        }
+        #[cfg(not(unix))]
+        Self {
+            mode: None,
+            owner: None,
+            mtime,
+        }
+    }
+
+    /// The columns of a line, each one followed by the separator.
+    pub fn format(&self, columns: &[Column]) -> String {
+        let mut line = String::new();
+        for column in columns {
+            let value = match column {
+                Column::Mode => self.mode.map(format_mode),
+                Column::Owner => self.owner.as_ref().map(ToString::to_string),
+                Column::Mtime => self.mtime.map(format_mtime),
+            };
+            line += value.as_deref().unwrap_or(UNKNOWN);
+            line.push(SEPARATOR);
+        }
+
+        line
+    }
+
+    /// Read the columns at the start of a line, returning the path left.
+    pub fn parse<'a>(line: &'a str, columns: &[Column]) -> Result<(Self, &'a str), String> {
+        let mut attributes = Self::default();
+        let mut rest = line;
+        for column in columns {
+            let (value, remaining) = rest
+                .split_once(SEPARATOR)
+                .ok_or_else(|| format!("missing the {:?} column", column))?;
+            rest = remaining;
+
+            let value = value.trim();
+            if value == UNKNOWN {
+                continue;
+            }
+
+            match column {
+                Column::Mode => attributes.mode = Some(parse_mode(value)?),
+                Column::Owner => attributes.owner = Some(parse_owner(value)?),
+                Column::Mtime => attributes.mtime = Some(parse_mtime(value)?),
+            }
+        }
+
+        Ok((attributes, rest))
+    }
+
+    /// What has to change for a file to go from `self` to `edited`.
+    pub fn changes(&self, edited: &Attributes) -> Vec<AttributeChange> {
+        let mut changes = Vec::new();
+        if let Some(owner) = &edited.owner {
+            let ids = |owner: &Owner| (owner.uid, owner.gid);
+            if self.owner.as_ref().map(ids) != Some(ids(owner)) {
+                changes.push(AttributeChange::Owner(owner.clone()));
+            }
+        }
+        // after chown, which may clear the setuid bits
+        if let Some(mode) = edited.mode.filter(|mode| Some(*mode) != self.mode) {
+            changes.push(AttributeChange::Mode(mode));
+        }
+        if let Some(mtime) = edited.mtime.filter(|mtime| Some(*mtime) != self.mtime) {
+            changes.push(AttributeChange::Mtime(mtime));
+        }
+
+        changes
+    }
+}
+
+fn parse_mode(value: &str) -> Result<u32, String> {
+    match u32::from_str_radix(value, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!(
+            "invalid mode `{}`, expected octal such as 0644",
+            value
+        )),
+    }
+}
+
+fn parse_mtime(value: &str) -> Result<i64, String> {
+    let invalid = || {
+        format!(
+            "invalid mtime `{}`, expected e.g. 2024-01-31T12:00:00",
+            value
+        )
+    };
+    let naive = NaiveDateTime::parse_from_str(value, MTIME_FORMAT).map_err(|_| invalid())?;
+
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.timestamp())
+        .ok_or_else(invalid)
+}
+
+#[cfg(unix)]
+fn owner_of(uid: u32, gid: u32) -> Owner {
+    let user = uzers::get_user_by_uid(uid)
+        .map(|user| user.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| uid.to_string());
+    let group = uzers::get_group_by_gid(gid)
+        .map(|group| group.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| gid.to_string());
+
+    Owner {
+        user,
+        group,
+        uid,
+        gid,
+    }
+}
+
+/// `user:group` with known names or numeric ids.
+#[cfg(unix)]
+fn parse_owner(value: &str) -> Result<Owner, String> {
+    let (user, group) = value
+        .split_once(':')
+        .ok_or_else(|| format!("invalid owner `{}`, expected user:group", value))?;
+
+    let uid = match user.parse::<u32>() {
+        Ok(uid) => uid,
+        Err(_) => uzers::get_user_by_name(user)
+            .map(|user| user.uid())
+            .ok_or_else(|| format!("unknown user `{}`", user))?,
+    };
+    let gid = match group.parse::<u32>() {
+        Ok(gid) => gid,
+        Err(_) => uzers::get_group_by_name(group)
+            .map(|group| group.gid())
+            .ok_or_else(|| format!("unknown group `{}`", group))?,
+    };
+
+    Ok(Owner {
+        user: user.to_owned(),
+        group: group.to_owned(),
+        uid,
+        gid,
+    })
+}
+
+#[cfg(not(unix))]
+fn parse_owner(value: &str) -> Result<Owner, String> {
+    Err(format!("owners are not supported here, got `{}`", value))
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::{AttributeChange, Attributes, Column};
+
+    const ALL: [Column; 3] = [Column::Mode, Column::Owner, Column::Mtime];
+
+    #[test]
+    fn test_parse_round_trip() {
+        let line = "0755\t0:0\t2024-01-31T12:00:00\tdir/a b.txt";
+        let (attributes, path) = Attributes::parse(line, &ALL).unwrap();
+
+        assert_eq!("dir/a b.txt", path);
+        assert_eq!(Some(0o755), attributes.mode);
+        assert_eq!(0, attributes.owner.as_ref().unwrap().uid);
+        assert_eq!("0755\t0:0\t2024-01-31T12:00:00\t", attributes.format(&ALL));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |line: &str| Attributes::parse(line, &ALL).map(|(attributes, _)| attributes);
+
+        assert!(parse("0644\t-\t-\ta.txt").is_ok());
+        assert!(parse("0844\t-\t-\ta.txt").is_err());
+        assert!(parse("17777\t-\t-\ta.txt").is_err());
+        assert!(parse("-\tno-such-user-here:0\t-\ta.txt").is_err());
+        assert!(parse("-\t-\t2024-13-01T00:00:00\ta.txt").is_err());
+        assert!(parse("0644\ta.txt").is_err());
+    }
+
+    #[test]
+    fn test_changes() {
+        let (original, _) = Attributes::parse("0644\t0:0\t-\ta", &ALL).unwrap();
+        let (edited, _) = Attributes::parse("0600\t0:0\t2024-01-31T12:00:00\ta", &ALL).unwrap();
+
+        let changes = original.changes(&edited);
+        assert_eq!(2, changes.len());
+        assert_eq!(AttributeChange::Mode(0o600), changes[0]);
+        assert!(matches!(changes[1], AttributeChange::Mtime(_)));
+        assert!(original.changes(&original).is_empty());
+    }
+}
@@ -1,4 +1,76 @@
-use crate::cli::Opts;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::columns::Column;
+use crate::transform::Transform;
+
+/// Names picked by `--on-conflict rename`, e.g. `file_1.txt`.
+pub const DEFAULT_AUTONAME_TEMPLATE: &str = "{stem}_{n}{ext}";
+
+/// Name of the project-local configuration file, looked up in the current dir.
+pub const PROJECT_CONFIG_FILE: &str = ".rnr-buf.toml";
+
+/// Keys a project config may set, they only change how paths are listed,
+/// named and shown.
+pub const PROJECT_KEYS: &[&str] = &[
+    "absolute",
+    "recursive",
+    "ignore_hidden",
+    "mkdir",
+    "quiet",
+    "modeline",
+    "case_insensitive",
+    "autoname_template",
+    "skip_changed",
+    "columns",
+    "two_column",
+    "transform",
+    "template",
+    "start",
+    "step",
+    "per_dir",
+];
+
+/// What to do when the destination of a rename already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ConflictStrategy {
+    /// Abort the whole run
+    #[default]
+    Fail,
+    /// Leave the source where it is
+    Skip,
+    /// Replace the existing target
+    Overwrite,
+    /// Move the existing target to `name~`, or a timestamped name
+    Backup,
+    /// Pick the next free name for the source, see `--autoname-template`
+    Rename,
+    /// Prompt for each conflict
+    Ask,
+}
+
+impl fmt::Display for ConflictStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConflictStrategy::Fail => "fail",
+            ConflictStrategy::Skip => "skip",
+            ConflictStrategy::Overwrite => "overwrite",
+            ConflictStrategy::Backup => "backup",
+            ConflictStrategy::Rename => "rename",
+            ConflictStrategy::Ask => "ask",
+        };
+
+        write!(f, "{}", name)
+    }
+}
 
 // Configuration options
 pub struct Config {
@@ -11,9 +83,8 @@ pub struct Config {
     /// Whether the command is recursive or not, default is false
     pub recursive: bool,
 
-    /// Whether to not fail in case of finding a target with the same filename,
-    /// it automatically adjusts it.
-    pub automatic_rename: bool,
+    /// What to do when a target with the same filename exists.
+    pub on_conflict: ConflictStrategy,
 
     /// Whether to ignore hidden files and directories (or not).
     pub ignore_hidden: bool,
@@ -26,6 +97,66 @@ pub struct Config {
 
     /// Whether to have terminal output or not. It will fail on error.
     pub quiet: bool,
+
+    /// Whether to write an editor modeline at the top of the buffer.
+    pub modeline: bool,
+
+    /// Whether to treat every target directory as case-insensitive, otherwise
+    /// it is detected per directory.
+    pub case_insensitive: bool,
+
+    /// Template used to pick a new name when renaming on conflict.
+    pub autoname_template: String,
+
+    /// Whether to skip, instead of aborting on, entries changed on disk since
+    /// they were listed.
+    pub skip_changed: bool,
+
+    /// Whether to rename tracked files through git, detected from the
+    /// current directory when not set.
+    pub git: Option<bool>,
+
+    /// Whether to rename tracked files with unstaged changes.
+    pub force: bool,
+
+    /// Whether to rewrite the symlinks pointing at renamed files.
+    pub fix_symlinks: bool,
+
+    /// Directory scanned for symlinks to rewrite.
+    pub symlink_root: PathBuf,
+
+    /// Globs of the text files whose references to renamed files are rewritten.
+    pub update_references: Vec<String>,
+
+    /// Directory scanned for text files referencing renamed files.
+    pub reference_root: PathBuf,
+
+    /// Extra columns of the buffer, whose edits change the files' attributes.
+    pub columns: Vec<Column>,
+
+    /// Whether emptied lines move their file to the trash.
+    pub delete: bool,
+
+    /// Whether buffer lines are `source<TAB>destination`, paired by source.
+    pub two_column: bool,
+
+    /// Rewrites of the file names applied to the buffer before editing, in order.
+    pub transform: Vec<Transform>,
+
+    /// Whether the buffer is used as is, without opening the editor.
+    pub no_editor: bool,
+
+    /// Template naming every listed file before editing, e.g. `trip-{n:03}{ext}`.
+    pub template: Option<String>,
+
+    /// First value of the `{n}` counter of `--template`.
+    pub start: u64,
+
+    /// Increment of the `{n}` counter of `--template`.
+    pub step: u64,
+
+    /// Whether the `{n}` counter of `--template` starts over in each directory.
+    pub per_dir: bool,
     // /// whether to follow symlinks or not.
     // pub follow_links: bool,
     //
@@ -59,31 +190,434 @@ pub struct Config {
     // pub ignore_files: vec<pathbuf>,
 }
 
-#[allow(dead_code)]
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Config {
     pub fn new() -> Self {
         Self {
-            automatic_rename: false,
+            on_conflict: ConflictStrategy::Fail,
             absolute: false,
             editor: None,
             ignore_hidden: false,
-            recursive: true,
-            mkdir: true,
+            recursive: false,
+            mkdir: false,
             yes: false,
             quiet: false,
+            modeline: false,
+            case_insensitive: false,
+            autoname_template: DEFAULT_AUTONAME_TEMPLATE.to_owned(),
+            skip_changed: false,
+            git: None,
+            force: false,
+            fix_symlinks: false,
+            symlink_root: PathBuf::from("."),
+            update_references: Vec::new(),
+            reference_root: PathBuf::from("."),
+            columns: Vec::new(),
+            delete: false,
+            two_column: false,
+            transform: Vec::new(),
+            no_editor: false,
+            template: None,
+            start: 1,
+            step: 1,
+            per_dir: false,
         }
     }
 
-    pub fn from_args(opts: &Opts) -> Self {
-        Self {
-            absolute: opts.absolute,
-            editor: opts.editor.clone(),
-            recursive: opts.recursive,
-            automatic_rename: opts.automatic_rename,
-            ignore_hidden: opts.ignore_hidden,
-            mkdir: opts.mkdir,
-            yes: opts.yes,
-            quiet: opts.quiet,
+    /// Build the configuration out of the config files, unless `no_config`,
+    /// and the values given on the command line.
+    ///
+    /// From lowest to highest precedence: built-in defaults, the user config,
+    /// the project config, the selected profile (user then project) and finally
+    /// the CLI flags.
+    pub fn load(
+        flags: &ConfigValues,
+        profile: Option<&str>,
+        no_config: bool,
+    ) -> Result<Self, anyhow::Error> {
+        let mut files = Vec::new();
+        if !no_config {
+            for path in config_paths() {
+                if path.is_file() {
+                    let file = ConfigFile::read(&path)?;
+                    if path == Path::new(PROJECT_CONFIG_FILE) {
+                        file.check_project()
+                            .with_context(|| format!("Invalid config file {:?}", path))?;
+                    }
+                    files.push(file);
+                }
+            }
+        }
+
+        Self::resolve(&files, flags, profile)
+    }
+
+    /// Merge the given config files, ordered from lowest to highest precedence.
+    pub(crate) fn resolve(
+        files: &[ConfigFile],
+        flags: &ConfigValues,
+        profile: Option<&str>,
+    ) -> Result<Self, anyhow::Error> {
+        let mut values = ConfigValues::default();
+        for file in files {
+            values.merge(&file.defaults);
+        }
+
+        if let Some(name) = profile {
+            let profiles: Vec<&ConfigValues> = files
+                .iter()
+                .filter_map(|file| file.profile.get(name))
+                .collect();
+
+            if profiles.is_empty() {
+                bail!("Profile `{}` is not defined in any config file", name);
+            }
+
+            for profile in profiles {
+                values.merge(profile);
+            }
+        }
+
+        values.merge(flags);
+
+        Ok(values.into_config(Self::new()))
+    }
+}
+
+/// Values that can be set from a config file, unset ones are left untouched.
+#[derive(Debug, Default, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigValues {
+    pub absolute: Option<bool>,
+    pub editor: Option<String>,
+    pub recursive: Option<bool>,
+    pub on_conflict: Option<ConflictStrategy>,
+    pub ignore_hidden: Option<bool>,
+    pub mkdir: Option<bool>,
+    pub yes: Option<bool>,
+    pub quiet: Option<bool>,
+    pub modeline: Option<bool>,
+    pub case_insensitive: Option<bool>,
+    pub autoname_template: Option<String>,
+    pub skip_changed: Option<bool>,
+    pub git: Option<bool>,
+    pub force: Option<bool>,
+    pub fix_symlinks: Option<bool>,
+    pub symlink_root: Option<PathBuf>,
+    pub update_references: Option<Vec<String>>,
+    pub reference_root: Option<PathBuf>,
+    pub columns: Option<Vec<Column>>,
+    pub delete: Option<bool>,
+    pub two_column: Option<bool>,
+    pub transform: Option<Vec<Transform>>,
+    pub no_editor: Option<bool>,
+    pub template: Option<String>,
+    pub start: Option<u64>,
+    pub step: Option<u64>,
+    pub per_dir: Option<bool>,
+}
+
+impl ConfigValues {
+    /// Names of the keys that have been set.
+    fn keys(&self) -> Vec<&'static str> {
+        macro_rules! set_keys {
+            ($($field:ident),*) => {
+                [$((stringify!($field), self.$field.is_some())),*]
+            };
+        }
+
+        set_keys!(
+            absolute,
+            editor,
+            recursive,
+            on_conflict,
+            ignore_hidden,
+            mkdir,
+            yes,
+            quiet,
+            modeline,
+            case_insensitive,
+            autoname_template,
+            skip_changed,
+            git,
+            force,
+            fix_symlinks,
+            symlink_root,
+            update_references,
+            reference_root,
+            columns,
+            delete,
+            two_column,
+            transform,
+            no_editor,
+            template,
+            start,
+            step,
+            per_dir
+        )
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(key, _)| key)
+        .collect()
+    }
+
+    /// Override the current values with the ones set in `other`.
+    pub fn merge(&mut self, other: &ConfigValues) {
+        macro_rules! merge_fields {
+            ($($field:ident),*) => {
+                $(
+                    if other.$field.is_some() {
+                        self.$field = other.$field.clone();
+                    }
+                )*
+            };
         }
+
+        merge_fields!(
+            absolute,
+            editor,
+            recursive,
+            on_conflict,
+            ignore_hidden,
+            mkdir,
+            yes,
+            quiet,
+            modeline,
+            case_insensitive,
+            autoname_template,
+            skip_changed,
+            git,
+            force,
+            fix_symlinks,
+            symlink_root,
+            update_references,
+            reference_root,
+            columns,
+            delete,
+            two_column,
+            transform,
+            no_editor,
+            template,
+            start,
+            step,
+            per_dir
+        );
+    }
+
+    /// Fill `config` with every value that has been set.
+    pub fn into_config(self, config: Config) -> Config {
+        Config {
+            absolute: self.absolute.unwrap_or(config.absolute),
+            editor: self.editor.or(config.editor),
+            recursive: self.recursive.unwrap_or(config.recursive),
+            on_conflict: self.on_conflict.unwrap_or(config.on_conflict),
+            ignore_hidden: self.ignore_hidden.unwrap_or(config.ignore_hidden),
+            mkdir: self.mkdir.unwrap_or(config.mkdir),
+            yes: self.yes.unwrap_or(config.yes),
+            quiet: self.quiet.unwrap_or(config.quiet),
+            modeline: self.modeline.unwrap_or(config.modeline),
+            case_insensitive: self.case_insensitive.unwrap_or(config.case_insensitive),
+            autoname_template: self.autoname_template.unwrap_or(config.autoname_template),
+            skip_changed: self.skip_changed.unwrap_or(config.skip_changed),
+            git: self.git.or(config.git),
+            force: self.force.unwrap_or(config.force),
+            fix_symlinks: self.fix_symlinks.unwrap_or(config.fix_symlinks),
+            symlink_root: self.symlink_root.unwrap_or(config.symlink_root),
+            update_references: self.update_references.unwrap_or(config.update_references),
+            reference_root: self.reference_root.unwrap_or(config.reference_root),
+            columns: self.columns.unwrap_or(config.columns),
+            delete: self.delete.unwrap_or(config.delete),
+            two_column: self.two_column.unwrap_or(config.two_column),
+            transform: self.transform.unwrap_or(config.transform),
+            no_editor: self.no_editor.unwrap_or(config.no_editor),
+            template: self.template.or(config.template),
+            start: self.start.unwrap_or(config.start),
+            step: self.step.unwrap_or(config.step),
+            per_dir: self.per_dir.unwrap_or(config.per_dir),
+        }
+    }
+}
+
+/// A TOML config file: top level defaults plus `[profile.<name>]` tables.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConfigFile {
+    pub defaults: ConfigValues,
+
+    pub profile: HashMap<String, ConfigValues>,
+}
+
+impl ConfigFile {
+    pub fn read(path: &Path) -> Result<Self, anyhow::Error> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+
+        Self::parse(&content).with_context(|| format!("Invalid config file {:?}", path))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, anyhow::Error> {
+        // split the profiles off by hand, unknown keys would go unnoticed in a
+        // flattened struct
+        let mut table: toml::Table = toml::from_str(content)?;
+        let profile = match table.remove("profile") {
+            Some(profile) => profile.try_into()?,
+            None => HashMap::new(),
+        };
+
+        Ok(Self {
+            defaults: table.try_into()?,
+            profile,
+        })
+    }
+
+    /// A project config comes with the directory, e.g. a cloned repository, so
+    /// it may only set the keys in [`PROJECT_KEYS`]: none of them picks the
+    /// command to run, skips the editor or the prompt, or lets a run touch
+    /// files outside of what has been listed.
+    pub fn check_project(&self) -> Result<(), anyhow::Error> {
+        for values in std::iter::once(&self.defaults).chain(self.profile.values()) {
+            if let Some(key) = values
+                .keys()
+                .into_iter()
+                .find(|key| !PROJECT_KEYS.contains(key))
+            {
+                bail!(
+                    "`{}` can't be set in {}, only in the user config",
+                    key,
+                    PROJECT_CONFIG_FILE
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Config files ordered from lowest to highest precedence.
+pub fn config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    };
+
+    if let Some(dir) = config_home {
+        paths.push(dir.join("rnr-buf").join("config.toml"));
+    }
+
+    paths.push(PathBuf::from(PROJECT_CONFIG_FILE));
+
+    paths
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Config, ConfigFile, ConfigValues, ConflictStrategy};
+
+    const USER_CONFIG: &str = r#"
+editor = "nano"
+mkdir = true
+
+[profile.photos]
+recursive = true
+on_conflict = "rename"
+"#;
+
+    const PROJECT_CONFIG: &str = r#"
+autoname_template = "{stem}-{n}{ext}"
+
+[profile.photos]
+mkdir = false
+"#;
+
+    fn files() -> Vec<ConfigFile> {
+        vec![
+            ConfigFile::parse(USER_CONFIG).expect("Failed to parse user config"),
+            ConfigFile::parse(PROJECT_CONFIG).expect("Failed to parse project config"),
+        ]
+    }
+
+    #[test]
+    fn test_project_overrides_user() {
+        let config = Config::resolve(&files(), &ConfigValues::default(), None)
+            .expect("Failed to resolve config");
+
+        assert_eq!(Some("nano".to_owned()), config.editor);
+        assert_eq!("{stem}-{n}{ext}", config.autoname_template);
+        assert!(config.mkdir);
+        assert!(!config.recursive);
+    }
+
+    #[test]
+    fn test_profile_and_flags() {
+        let flags = ConfigValues {
+            editor: Some("emacs".to_owned()),
+            yes: Some(true),
+            ..ConfigValues::default()
+        };
+        let config =
+            Config::resolve(&files(), &flags, Some("photos")).expect("Failed to resolve config");
+
+        assert_eq!(Some("emacs".to_owned()), config.editor);
+        assert!(config.recursive);
+        assert!(!config.mkdir);
+        assert_eq!(ConflictStrategy::Rename, config.on_conflict);
+        assert!(config.yes);
+    }
+
+    #[test]
+    fn test_no_flags() {
+        let flags = ConfigValues {
+            recursive: Some(false),
+            mkdir: Some(true),
+            ..ConfigValues::default()
+        };
+        let config =
+            Config::resolve(&files(), &flags, Some("photos")).expect("Failed to resolve config");
+
+        assert!(!config.recursive);
+        assert!(config.mkdir);
+    }
+
+    #[test]
+    fn test_project_unsafe_keys() {
+        let unsafe_keys = [
+            "editor = \"sh\"",
+            "[profile.photos]\nyes = true",
+            "delete = true",
+            "no_editor = true",
+            "on_conflict = \"overwrite\"",
+            "force = true",
+            "git = true",
+            "fix_symlinks = true",
+            "symlink_root = \"/\"",
+            "update_references = [\"*\"]",
+            "reference_root = \"/\"",
+        ];
+        for content in unsafe_keys {
+            let file = ConfigFile::parse(content).expect("Failed to parse project config");
+            let error = file.check_project().unwrap_err().to_string();
+
+            assert!(error.contains("only in the user config"), "{}", error);
+        }
+
+        let file = ConfigFile::parse(PROJECT_CONFIG).expect("Failed to parse project config");
+        assert!(file.check_project().is_ok());
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        assert!(ConfigFile::parse("mkdirs = true").is_err());
+        assert!(ConfigFile::parse("[profile.photos]\nrecursve = true").is_err());
+    }
+
+    #[test]
+    fn test_unknown_profile() {
+        assert!(Config::resolve(&files(), &ConfigValues::default(), Some("music")).is_err());
     }
 }
@@ -1,3 +1,179 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
 pub fn error_string() -> String {
     "[rnr-buf error]: ".to_owned()
 }
+
+/// Errors returned by the library. Lines are the ones of the edited buffer,
+/// `from` and `to` the source and destination of a rename.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+
+    #[error("line {line}: duplicate path {path:?}, already on line {first_line}")]
+    DuplicatePath {
+        line: usize,
+        first_line: usize,
+        path: PathBuf,
+    },
+
+    #[error("{modified} paths given for {original} files, lines cannot be added or removed (empty them to delete with --delete)")]
+    LineCount { original: usize, modified: usize },
+
+    #[error("the line for {path:?} is missing")]
+    MissingLine { path: PathBuf },
+
+    #[error("line {line}: {from:?} -> {to:?}, the target exists")]
+    TargetExists {
+        line: usize,
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    #[error("line {line}: {from:?} -> {to:?}, the target dirs do not exist")]
+    MissingDir {
+        line: usize,
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    #[error("lines {other_line} and {line}: {other:?} and {path:?} collide on a case-insensitive filesystem")]
+    CaseCollision {
+        line: usize,
+        other_line: usize,
+        path: PathBuf,
+        other: PathBuf,
+    },
+
+    #[error("lines {other_line} and {line}: both are renamed to {to:?}")]
+    SameDestination {
+        line: usize,
+        other_line: usize,
+        to: PathBuf,
+    },
+
+    #[error("lines {line} and {other_line}: {from:?} is renamed onto {to:?}, which is skipped")]
+    SkippedTarget {
+        line: usize,
+        other_line: usize,
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    #[error("lines {line} and {other_line}: {path:?} is inside {deleted:?}, which is deleted")]
+    InsideDeleted {
+        line: usize,
+        other_line: usize,
+        path: PathBuf,
+        deleted: PathBuf,
+    },
+
+    #[error("line {line}: {from:?} has vanished")]
+    SourceVanished { line: usize, from: PathBuf },
+
+    #[error("line {line}: {from:?} has been replaced or modified")]
+    SourceChanged { line: usize, from: PathBuf },
+
+    #[error("line {line}: {from:?} -> {to:?}, the target has appeared")]
+    TargetAppeared {
+        line: usize,
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    #[error("line {line}: {from:?} has unstaged changes, rename it anyway with --force")]
+    UnstagedChanges { line: usize, from: PathBuf },
+
+    #[error("{path:?} would be renamed to an empty name")]
+    EmptyName { path: PathBuf },
+
+    #[error("invalid template `{template}`: {message}")]
+    Template { template: String, message: String },
+
+    #[error("invalid glob `{glob}`: {message}")]
+    Glob { glob: String, message: String },
+
+    #[error("cannot look for {count} references: {message}")]
+    TooManyReferences { count: usize, message: String },
+
+    #[error("line {line}: {from:?} -> {to:?}, permission denied")]
+    PermissionDenied {
+        line: usize,
+        from: PathBuf,
+        to: PathBuf,
+        #[source]
+        error: io::Error,
+    },
+
+    #[error("line {line}: {from:?} -> {to:?} failed")]
+    Io {
+        line: usize,
+        from: PathBuf,
+        to: PathBuf,
+        #[source]
+        error: io::Error,
+    },
+
+    #[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"))]
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Wrap an I/O error of a rename, telling permission errors apart.
+    pub fn io(line: usize, from: &Path, to: &Path, error: io::Error) -> Self {
+        let (from, to) = (from.to_path_buf(), to.to_path_buf());
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied {
+                line,
+                from,
+                to,
+                error,
+            },
+            _ => Error::Io {
+                line,
+                from,
+                to,
+                error,
+            },
+        }
+    }
+
+    /// Fail with every collected error, if any.
+    pub fn from_list(mut errors: Vec<Error>) -> Result<(), Error> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Error::Multiple(errors)),
+        }
+    }
+
+    /// Line of the buffer the error refers to, if any.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Error::Parse { line, .. }
+            | Error::DuplicatePath { line, .. }
+            | Error::TargetExists { line, .. }
+            | Error::MissingDir { line, .. }
+            | Error::CaseCollision { line, .. }
+            | Error::SameDestination { line, .. }
+            | Error::SkippedTarget { line, .. }
+            | Error::InsideDeleted { line, .. }
+            | Error::SourceVanished { line, .. }
+            | Error::SourceChanged { line, .. }
+            | Error::TargetAppeared { line, .. }
+            | Error::UnstagedChanges { line, .. }
+            | Error::PermissionDenied { line, .. }
+            | Error::Io { line, .. } => Some(*line),
+            Error::LineCount { .. }
+            | Error::MissingLine { .. }
+            | Error::EmptyName { .. }
+            | Error::Template { .. }
+            | Error::Glob { .. }
+            | Error::TooManyReferences { .. }
+            | Error::Multiple(_) => None,
+        }
+    }
+}
@@ -1,26 +1,61 @@
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::{env, fs};
 
-use anyhow::bail;
-use dialoguer::Confirm;
-use std::io::{Read, Write};
-use tempfile::NamedTempFile;
+use dialoguer::{Confirm, Select};
+use std::io::{self, Write};
+use tempfile::Builder;
 
 use walkdir::WalkDir;
 
-use crate::config::Config;
-use crate::filelist::FileList;
+use crate::columns::{AttributeChange, Attributes, SEPARATOR};
+use crate::config::{Config, ConflictStrategy};
+use crate::errors::Error;
+use crate::filelist::{FileDirPosition, FileList, Fingerprint, COMMENT};
 use crate::filesystem::{
-    all_dirs_exist, create_all_dirs, file_autonamer, get_last_component, has_hidden,
+    autoname_template, backup_name, file_autonamer, has_hidden, is_case_only_change, missing_dirs,
+    release_temp, rename_via_temp, reserve_temp, FileSystem,
 };
+use crate::plan::{linked_groups, order_activities, temp_moves, validate_plan, CaseSensitivity};
+use crate::references::{apply_reference_updates, find_reference_updates, ReferenceUpdate};
+use crate::symlinks::{find_symlink_fixes, rewrite_symlink, SymlinkFix};
+
+/// Suffix of the buffer given to the editor, used for filetype detection.
+pub const BUFFER_SUFFIX: &str = ".rnr-buf";
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct Activity {
     pub mkdirs: Vec<PathBuf>,
     pub source: PathBuf,
     pub given_destination_path: PathBuf,
     pub destination: PathBuf,
+    /// Line of the edited buffer the destination comes from.
+    pub line: usize,
+    /// The destination is the source itself with a different case.
+    pub case_only: bool,
+    /// How an existing destination has been dealt with.
+    pub on_conflict: Option<ConflictStrategy>,
+    /// Where the existing destination is moved before the rename.
+    pub backup: Option<PathBuf>,
+    /// State of the source when it was listed, checked again before running.
+    pub fingerprint: Option<Fingerprint>,
+    /// Symlinks pointing at the source, rewritten once every rename has run.
+    pub symlinks: Vec<SymlinkFix>,
+    /// Text files referencing the source, rewritten once every rename has run.
+    pub references: Vec<ReferenceUpdate>,
+    /// Edited columns, applied to the destination after the rename.
+    pub changes: Vec<AttributeChange>,
+    /// The line has been emptied, the source goes to the trash.
+    pub delete: bool,
+}
+
+impl Activity {
+    /// Wether the activity moves its source, it may only change attributes.
+    pub fn is_rename(&self) -> bool {
+        !self.delete && self.source != self.destination
+    }
 }
 
 pub fn list_files(paths: Vec<String>, config: &Config) -> Result<FileList, anyhow::Error> {
@@ -51,6 +86,29 @@ pub fn list_files(paths: Vec<String>, config: &Config) -> Result<FileList, anyho
         }
     }
 
+    // the first tab of a line ends its left column
+    if config.two_column {
+        if let Some(file) = list
+            .list
+            .iter()
+            .find(|file| file.source.to_string_lossy().contains(SEPARATOR))
+        {
+            anyhow::bail!(
+                "{:?} contains a tab, which separates the columns of --two-column",
+                file.source
+            );
+        }
+    }
+
+    list.columns = config.columns.clone();
+    list.two_column = config.two_column;
+    for file in list.list.iter_mut() {
+        file.fingerprint = Fingerprint::of(&file.source).ok();
+        if !list.columns.is_empty() {
+            file.attributes = Attributes::of(&file.source);
+        }
+    }
+
     list.enumerate();
 
     Ok(list)
@@ -66,7 +124,13 @@ pub fn open_editor(outcome: &str, config: &Config) -> Result<String, anyhow::Err
         editor = config.editor.clone().unwrap();
     }
 
-    let mut temp_file = NamedTempFile::new()?;
+    let mut temp_file = Builder::new()
+        .prefix("rnr-buf-")
+        .suffix(BUFFER_SUFFIX)
+        .tempfile()?;
+    if config.modeline {
+        writeln!(temp_file, "{}", modeline())?;
+    }
     write!(temp_file, "{}", outcome)?;
     temp_file.flush()?;
 
@@ -85,105 +149,607 @@ pub fn open_editor(outcome: &str, config: &Config) -> Result<String, anyhow::Err
         .wait_with_output()
         .expect("Failed to wait for command");
 
-    // Re-open it.
-    let mut file_edited = temp_file.reopen()?;
-
-    let mut buf = String::new();
-    file_edited.read_to_string(&mut buf)?;
+    // Read it back by path, editors may have replaced the file while saving.
+    let buf = fs::read_to_string(temp_file_path)?;
 
     Ok(buf)
 }
 
-pub fn batch_operations(
-    original: &FileList,
-    modified: &FileList,
+/// Comment understood by both Emacs and Vim to select the rnr-buf filetype.
+pub fn modeline() -> String {
+    format!(
+        "{} -*- mode: rnr-buf -*- vim: set filetype=rnr-buf :",
+        COMMENT
+    )
+}
+
+/// Listed entries whose line changed, and deleted ones with their line.
+type Pairing<'a> = (
+    Vec<(&'a FileDirPosition, &'a FileDirPosition)>,
+    Vec<(&'a FileDirPosition, usize)>,
+);
+
+/// Pair every original line with the edited line at the same position, only
+/// emptied lines are deletions, removed ones would shift the others.
+fn pair_by_position<'a>(
+    original: &'a FileList,
+    modified: &'a FileList,
     config: &Config,
-) -> Result<Vec<Activity>, anyhow::Error> {
-    if original.list.len() != modified.list.len() {
-        bail!("Files are not matching creation and deletion are not allowed");
+) -> Result<Pairing<'a>, Error> {
+    let line_count = Error::LineCount {
+        original: original.list.len(),
+        modified: modified.list.len(),
+    };
+    if original.list.len() < modified.list.len()
+        || (original.list.len() > modified.list.len() && !config.delete)
+    {
+        return Err(line_count);
     }
 
-    let mut outcome: Vec<Activity> = Vec::new();
-
+    let blank_lines = if config.delete {
+        modified.blank_lines()
+    } else {
+        HashMap::new()
+    };
+    let mut changes = Vec::new();
+    let mut deleted = Vec::new();
     for path in original.list.iter() {
-        let found = modified.get_by_file(&path.source);
-        if found.is_some() {
-            continue;
+        let index_element = match modified.get_by_index(path.position) {
+            Some(element) => element,
+            None => match blank_lines.get(&path.position) {
+                Some(&line) => {
+                    deleted.push((path, line));
+                    continue;
+                }
+                None => {
+                    return Err(Error::MissingLine {
+                        path: path.source.clone(),
+                    })
+                }
+            },
+        };
+
+        if index_element.source != path.source || index_element.attributes != path.attributes {
+            changes.push((path, index_element));
         }
+    }
 
-        // always found because the 2 buffers are the same size
-        let index_element = modified.get_by_index(path.position).unwrap();
-        let mut destination = index_element.source.clone();
+    // every edited line has to take the place of a listed one
+    if original.list.len() - deleted.len() != modified.list.len() {
+        return Err(line_count);
+    }
 
-        // file has been renamed or moved
-        let modified_component = get_last_component(&index_element.source);
-        let original_component = get_last_component(&path.source);
+    Ok((changes, deleted))
+}
+
+/// Pair every original line with the edited line keeping it as left column,
+/// wherever it has been moved in the buffer.
+fn pair_by_origin<'a>(
+    original: &'a FileList,
+    modified: &'a FileList,
+    config: &Config,
+) -> Result<Pairing<'a>, Error> {
+    // the parser has checked the left column against the listing
+    let mut changes = Vec::new();
+    let mut deleted = Vec::new();
+    for path in original.list.iter() {
+        let index_element = match modified.get_by_origin(&path.source) {
+            Some(element) => element,
+            None => {
+                return Err(Error::MissingLine {
+                    path: path.source.clone(),
+                })
+            }
+        };
 
-        // components match, it has been moved else renamed
-        if modified_component == original_component {
-            // move and/or create
-            // check if a file system with the same name exists
-            if index_element.source.exists() && !config.automatic_rename {
-                bail!("{:?} exists in the system", index_element.source);
+        if index_element.source.as_os_str().is_empty() {
+            if !config.delete {
+                return Err(Error::MissingLine {
+                    path: path.source.clone(),
+                });
             }
+            deleted.push((path, index_element.line));
+        } else if index_element.source != path.source || index_element.attributes != path.attributes
+        {
+            changes.push((path, index_element));
+        }
+    }
+
+    Ok((changes, deleted))
+}
+
+pub fn batch_operations(
+    fs: &dyn FileSystem,
+    original: &FileList,
+    modified: &FileList,
+    config: &Config,
+) -> Result<Vec<Activity>, Error> {
+    let mut outcome: Vec<Activity> = Vec::new();
+    let template_error = |err: anyhow::Error| Error::Template {
+        template: config.autoname_template.clone(),
+        message: format!("{:#}", err),
+    };
+    let autoname = autoname_template(&config.autoname_template).map_err(template_error)?;
+    let mut case_sensitivity = CaseSensitivity::new(fs, config);
+
+    let (changes, deleted) = if modified.two_column {
+        pair_by_origin(original, modified, config)?
+    } else {
+        pair_by_position(original, modified, config)?
+    };
+
+    for &(path, line) in deleted.iter() {
+        outcome.push(Activity {
+            mkdirs: Vec::new(),
+            source: path.source.clone(),
+            given_destination_path: path.source.clone(),
+            destination: path.source.clone(),
+            line,
+            case_only: false,
+            on_conflict: None,
+            backup: None,
+            fingerprint: path.fingerprint,
+            symlinks: Vec::new(),
+            references: Vec::new(),
+            changes: Vec::new(),
+            delete: true,
+        });
+    }
 
-            if index_element.source.exists() && config.automatic_rename {
-                destination = file_autonamer(&index_element.source);
+    // sources moved or deleted by the plan free their path for other entries
+    let vacated: HashSet<String> = changes
+        .iter()
+        .filter(|(path, index_element)| path.source != index_element.source)
+        .map(|(path, _)| &path.source)
+        .chain(deleted.iter().map(|(path, _)| &path.source))
+        .map(|source| case_sensitivity.key(source))
+        .collect();
+    let occupied = |destination: &PathBuf, case_sensitivity: &mut CaseSensitivity| {
+        fs.exists(destination) && !vacated.contains(&case_sensitivity.key(destination))
+    };
+    let mut reserved: HashSet<PathBuf> = HashSet::new();
+
+    for (path, index_element) in changes {
+        let mut destination = index_element.source.clone();
+
+        if destination == path.source {
+            let changes = path.attributes.changes(&index_element.attributes);
+            if !changes.is_empty() {
+                outcome.push(Activity {
+                    mkdirs: Vec::new(),
+                    source: path.source.clone(),
+                    given_destination_path: destination.clone(),
+                    destination,
+                    line: index_element.line,
+                    case_only: false,
+                    on_conflict: None,
+                    backup: None,
+                    fingerprint: path.fingerprint,
+                    symlinks: Vec::new(),
+                    references: Vec::new(),
+                    changes,
+                    delete: false,
+                });
             }
+            continue;
+        }
+
+        if is_case_only_change(&path.source, &destination)
+            && case_sensitivity.is_insensitive(&destination)
+        {
+            outcome.push(Activity {
+                mkdirs: Vec::new(),
+                source: path.source.clone(),
+                given_destination_path: destination.clone(),
+                destination,
+                line: index_element.line,
+                case_only: true,
+                on_conflict: None,
+                backup: None,
+                fingerprint: path.fingerprint,
+                symlinks: Vec::new(),
+                references: Vec::new(),
+                changes: path.attributes.changes(&index_element.attributes),
+                delete: false,
+            });
+            continue;
         }
 
-        if destination.exists() && config.automatic_rename {
-            destination = file_autonamer(&destination);
+        let mut on_conflict = None;
+        let mut backup = None;
+        if occupied(&destination, &mut case_sensitivity) {
+            let strategy = match config.on_conflict {
+                ConflictStrategy::Ask => {
+                    ask_conflict(&path.source, &destination).map_err(|err| {
+                        Error::io(index_element.line, &path.source, &destination, err)
+                    })?
+                }
+                strategy => strategy,
+            };
+
+            match strategy {
+                ConflictStrategy::Fail | ConflictStrategy::Ask => {
+                    return Err(Error::TargetExists {
+                        line: index_element.line,
+                        from: path.source.clone(),
+                        to: destination,
+                    });
+                }
+                ConflictStrategy::Backup => backup = Some(backup_name(fs, &destination)),
+                ConflictStrategy::Rename => {
+                    destination = file_autonamer(fs, &destination, &autoname, &reserved)
+                        .map_err(template_error)?
+                }
+                ConflictStrategy::Skip | ConflictStrategy::Overwrite => {}
+            }
+
+            on_conflict = Some(strategy);
         }
+        reserved.insert(destination.clone());
 
         let mut activity = Activity {
             mkdirs: Vec::new(),
             source: path.source.clone(),
             given_destination_path: index_element.source.clone(),
             destination: destination.clone(),
+            line: index_element.line,
+            case_only: false,
+            on_conflict,
+            backup,
+            fingerprint: path.fingerprint,
+            symlinks: Vec::new(),
+            references: Vec::new(),
+            changes: path.attributes.changes(&index_element.attributes),
+            delete: false,
+        };
+
+        if on_conflict == Some(ConflictStrategy::Skip) {
+            outcome.push(activity);
+            continue;
+        }
+
+        activity.mkdirs = missing_dirs(fs, &destination);
+        if !activity.mkdirs.is_empty() && !config.mkdir {
+            return Err(Error::MissingDir {
+                line: activity.line,
+                from: activity.source,
+                to: destination,
+            });
+        }
+
+        let replaces_target = matches!(
+            on_conflict,
+            Some(ConflictStrategy::Overwrite | ConflictStrategy::Backup)
+        );
+        if occupied(&destination, &mut case_sensitivity) && !replaces_target {
+            return Err(Error::TargetExists {
+                line: activity.line,
+                from: activity.source,
+                to: destination,
+            });
+        }
+
+        outcome.push(activity);
+    }
+
+    finish_plan(fs, modified, outcome, config, &mut case_sensitivity)
+}
+
+/// Checks shared by every plan once its activities are known, then the
+/// symlinks and references to fix and the order to run them in.
+pub(crate) fn finish_plan(
+    fs: &dyn FileSystem,
+    modified: &FileList,
+    mut outcome: Vec<Activity>,
+    config: &Config,
+    case_sensitivity: &mut CaseSensitivity,
+) -> Result<Vec<Activity>, Error> {
+    if !config.force {
+        let unstaged = outcome
+            .iter()
+            .filter(|activity| activity.on_conflict != Some(ConflictStrategy::Skip))
+            .filter(|activity| fs.has_unstaged_changes(&activity.source))
+            .map(|activity| Error::UnstagedChanges {
+                line: activity.line,
+                from: activity.source.clone(),
+            })
+            .collect();
+        Error::from_list(unstaged)?;
+    }
+
+    validate_plan(modified, &outcome, case_sensitivity)?;
+
+    if config.fix_symlinks {
+        find_symlink_fixes(fs, &config.symlink_root, &mut outcome);
+    }
+    if !config.update_references.is_empty() {
+        find_reference_updates(
+            fs,
+            &config.reference_root,
+            &config.update_references,
+            &mut outcome,
+        )?;
+    }
+
+    Ok(order_activities(fs, outcome))
+}
+
+/// Prompt for what to do with a destination that already exists.
+fn ask_conflict(source: &Path, destination: &Path) -> io::Result<ConflictStrategy> {
+    let choices = [
+        ConflictStrategy::Skip,
+        ConflictStrategy::Overwrite,
+        ConflictStrategy::Backup,
+        ConflictStrategy::Rename,
+        ConflictStrategy::Fail,
+    ];
+
+    let selection = Select::new()
+        .with_prompt(format!(
+            "{:?} -> {:?}: the target exists",
+            source, destination
+        ))
+        .items(&choices)
+        .default(0)
+        .interact()
+        .map_err(|dialoguer::Error::IO(err)| err)?;
+
+    Ok(choices[selection])
+}
+
+/// Why an activity cannot run anymore, the tree may have changed while the
+/// buffer was being edited. `vacated` and `created` are the paths moved away
+/// and made by the activities running before it, the first by their
+/// [`CaseSensitivity::key`].
+fn changed_since_listing(
+    fs: &dyn FileSystem,
+    activity: &Activity,
+    vacated: &HashSet<String>,
+    created: &HashSet<&PathBuf>,
+    case_sensitivity: &mut CaseSensitivity,
+) -> Option<Error> {
+    // a temporary name is checked through the rename making it
+    if !created.contains(&activity.source) {
+        let current = match fs.metadata(&activity.source) {
+            Ok(current) => current,
+            Err(_) => {
+                return Some(Error::SourceVanished {
+                    line: activity.line,
+                    from: activity.source.clone(),
+                })
+            }
         };
 
-        let all_dirs_exist = all_dirs_exist(&destination);
-        let mut dir_missing_created = false;
-        if !all_dirs_exist {
-            if config.mkdir {
-                dir_missing_created = true;
-                activity.mkdirs = create_all_dirs(&destination)?;
+        if let Some(listed) = &activity.fingerprint {
+            if !listed.matches(&current) {
+                return Some(Error::SourceChanged {
+                    line: activity.line,
+                    from: activity.source.clone(),
+                });
             }
+        }
+    }
 
-            if !config.quiet {
-                bail!("{:?} dirs do not exist", destination);
+    let replaces_target = matches!(
+        activity.on_conflict,
+        Some(ConflictStrategy::Overwrite | ConflictStrategy::Backup)
+    );
+    if activity.is_rename()
+        && !activity.case_only
+        && !replaces_target
+        && !vacated.contains(&case_sensitivity.key(&activity.destination))
+        && fs.exists(&activity.destination)
+    {
+        return Some(Error::TargetAppeared {
+            line: activity.line,
+            from: activity.source.clone(),
+            to: activity.destination.clone(),
+        });
+    }
+
+    None
+}
+
+/// Check the whole plan against the tree before anything runs, returning the
+/// groups of activities to leave out with `--skip-changed`.
+fn check_before_running(
+    fs: &dyn FileSystem,
+    outcome: &[Activity],
+    groups: &[usize],
+    config: &Config,
+) -> Result<HashSet<usize>, Error> {
+    let mut problems = Vec::new();
+    let mut skipped = HashSet::new();
+    let mut case_sensitivity = CaseSensitivity::new(fs, config);
+    let mut vacated = HashSet::new();
+    let mut created = HashSet::new();
+    for (activity, group) in outcome.iter().zip(groups) {
+        if activity.on_conflict == Some(ConflictStrategy::Skip) {
+            continue;
+        }
+
+        if let Some(err) =
+            changed_since_listing(fs, activity, &vacated, &created, &mut case_sensitivity)
+        {
+            if !config.skip_changed {
+                problems.push(err);
+            } else if skipped.insert(*group) && !config.quiet {
+                eprintln!("{}, skipped along with the renames linked to it", err);
             }
         }
 
-        if !destination.exists() && (all_dirs_exist || dir_missing_created) {
-            outcome.push(activity);
-        } else if !config.quiet {
-            bail!("A file `{:?}` exists", destination);
+        if activity.delete || activity.is_rename() {
+            vacated.insert(case_sensitivity.key(&activity.source));
+        }
+        if activity.is_rename() {
+            created.insert(&activity.destination);
         }
     }
+    Error::from_list(problems)?;
+
+    Ok(skipped)
+}
 
-    Ok(outcome)
+pub fn perfom_operations(
+    fs: &dyn FileSystem,
+    outcome: &[Activity],
+    config: &Config,
+) -> Result<(), Error> {
+    perfom_operations_tracked(fs, outcome, config, &mut Vec::new())
 }
 
-pub fn perfom_operations(outcome: &Vec<Activity>, config: &Config) -> Result<(), anyhow::Error> {
-    for activity in outcome {
+/// Same as [`perfom_operations`], pushing the index of each activity to
+/// `completed` once it has run.
+pub fn perfom_operations_tracked(
+    fs: &dyn FileSystem,
+    outcome: &[Activity],
+    config: &Config,
+    completed: &mut Vec<usize>,
+) -> Result<(), Error> {
+    // a chain or cycle stopped halfway would leave files at temporary names
+    let groups = linked_groups(outcome);
+    let skipped = check_before_running(fs, outcome, &groups, config)?;
+    let mut confirmed: HashMap<usize, bool> = HashMap::new();
+    let temp_moves = temp_moves(outcome);
+    let mut reserved = HashSet::new();
+
+    let mut symlinks = Vec::new();
+    let mut references = Vec::new();
+    for (i, (activity, group)) in outcome.iter().zip(&groups).enumerate() {
+        if skipped.contains(group) {
+            continue;
+        }
+
         if !config.quiet {
-            println!("{:?} -> {:?}", activity.source, activity.destination);
+            match (&activity.on_conflict, &activity.backup) {
+                (Some(strategy), Some(backup)) => println!(
+                    "{:?} -> {:?} ({}: {:?})",
+                    activity.source, activity.destination, strategy, backup
+                ),
+                (Some(strategy), None) => println!(
+                    "{:?} -> {:?} ({})",
+                    activity.source, activity.destination, strategy
+                ),
+                _ if activity.delete => println!("{:?} (delete)", activity.source),
+                _ if !activity.is_rename() => println!("{:?}", activity.source),
+                _ => println!("{:?} -> {:?}", activity.source, activity.destination),
+            }
+
+            for change in &activity.changes {
+                println!("  {}", change);
+            }
+
+            for fix in &activity.symlinks {
+                println!(
+                    "  symlink {:?}: {:?} -> {:?}",
+                    fix.link, fix.old_target, fix.new_target
+                );
+            }
+            for update in &activity.references {
+                for (number, old, new) in &update.lines {
+                    println!("  {}:{}", update.file.display(), number);
+                    println!("  - {}", old);
+                    println!("  + {}", new);
+                }
+            }
+        }
+
+        if activity.on_conflict == Some(ConflictStrategy::Skip) {
+            continue;
+        }
+
+        // the answer holds for every rename linked to this one
+        let confirmation = config.yes
+            || *confirmed.entry(*group).or_insert_with(|| {
+                let prompt = match groups.iter().filter(|other| *other == group).count() {
+                    1 => "Are you sure?".to_owned(),
+                    linked => format!("Are you sure? This runs {} linked renames", linked),
+                };
+                Confirm::new().with_prompt(prompt).interact().unwrap()
+            });
+
+        if !confirmation {
+            continue;
+        }
+
+        // dirs shared with an earlier activity may exist by now
+        for dir in &activity.mkdirs {
+            if fs.is_dir(dir) {
+                continue;
+            }
+
+            if !config.quiet {
+                println!("mkdir {:?}", dir);
+            }
+            fs.create_dir(dir)
+                .map_err(|err| Error::io(activity.line, &activity.source, dir, err))?;
         }
 
-        let mut confirmation = false;
-        if !config.yes || !config.quiet {
-            confirmation = Confirm::new()
-                .with_prompt("Are you sure?")
-                .interact()
-                .unwrap();
+        if let Some(backup) = &activity.backup {
+            fs.rename(&activity.destination, backup)
+                .map_err(|err| Error::io(activity.line, &activity.destination, backup, err))?;
         }
 
-        if confirmation {
-            fs::rename(&activity.source, &activity.destination)?;
+        if activity.delete {
+            let trashed = fs
+                .trash(&activity.source)
+                .map_err(|err| Error::io(activity.line, &activity.source, &activity.source, err))?;
+            completed.push(i);
+            if !config.quiet {
+                println!("trashed {:?}", trashed);
+            }
+            continue;
+        }
+
+        let renamed = if !activity.is_rename() {
+            Ok(())
+        } else if activity.case_only {
+            rename_via_temp(fs, &activity.source, &activity.destination)
+        } else if temp_moves.contains(&i) {
+            reserve_temp(fs, &activity.destination)
+                .and_then(|_| fs.rename(&activity.source, &activity.destination))
+        } else {
+            fs.rename(&activity.source, &activity.destination)
+        };
+        renamed.map_err(|err| {
+            Error::io(activity.line, &activity.source, &activity.destination, err)
+        })?;
+        completed.push(i);
+
+        if temp_moves.contains(&i) {
+            reserved.insert(&activity.destination);
+        } else if reserved.remove(&activity.source) {
+            release_temp(fs, &activity.source).map_err(|err| {
+                Error::io(activity.line, &activity.source, &activity.destination, err)
+            })?;
+        }
+
+        for change in &activity.changes {
+            let destination = &activity.destination;
+            let changed = match change {
+                AttributeChange::Mode(mode) => fs.set_mode(destination, *mode),
+                AttributeChange::Owner(owner) => fs.set_owner(destination, owner.uid, owner.gid),
+                AttributeChange::Mtime(mtime) => {
+                    fs.set_mtime(destination, AttributeChange::mtime(*mtime))
+                }
+            };
+            changed.map_err(|err| Error::io(activity.line, destination, destination, err))?;
         }
+
+        symlinks.extend(activity.symlinks.iter().map(|fix| (activity.line, fix)));
+        references.extend(
+            activity
+                .references
+                .iter()
+                .map(|update| (activity.line, update)),
+        );
+    }
+
+    // links may be renamed themselves, they are rewritten at their final place
+    for (line, fix) in symlinks {
+        rewrite_symlink(fs, fix).map_err(|err| Error::io(line, &fix.link, &fix.new_target, err))?;
     }
+    apply_reference_updates(fs, &references)?;
 
     Ok(())
 }
@@ -191,10 +757,13 @@ pub fn perfom_operations(outcome: &Vec<Activity>, config: &Config) -> Result<(),
 #[cfg(test)]
 mod test {
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
     use super::Activity;
-    use crate::config::Config;
+    use crate::config::{Config, ConflictStrategy};
+    use crate::errors::Error;
+    use crate::filesystem::{FileSystem, RealFileSystem};
+    use crate::memory::MemoryFileSystem;
 
     #[derive(Debug)]
     pub struct TestingActivity<'a> {
@@ -240,8 +809,7 @@ mod test {
                     use crate::filelist::FileList;
                     use crate::exec::batch_operations;
 
-                    let tempdir = tempfile::tempdir().expect("Error creating temp directory");
-                    let temp_path = tempdir.path().to_str().unwrap();
+                    let temp_path = "tmp";
 
                     let mock_dir = format!("{}/other", temp_path);
                     let mock_files: Vec<String> = vec![
@@ -266,10 +834,7 @@ mod test {
                     //         |
                     //         - other_file_1.txt
 
-                    fs::create_dir(&mock_dir).expect("Error creating mock directory...");
-                    for file in &mock_files {
-                        fs::File::create(file).expect("Error creating mock file...");
-                    }
+                    let fs = MemoryFileSystem::with_files(&mock_files);
 
                     let mut before_list_raw_string = String::new();
                     for string in &mut $before_list {
@@ -283,7 +848,7 @@ mod test {
 
                     match (FileList::new_from_raw(before_list_raw_string), FileList::new_from_raw(after_list_raw_string)) {
                         (Ok(before_list), Ok(after_list)) => {
-                            match batch_operations(&before_list, &after_list, &$config) {
+                            match batch_operations(&fs, &before_list, &after_list, &$config) {
                                 Ok(outcome) => {
                                     for (result, expected) in outcome.iter().zip($activities.iter()) {
                                         assert_compare_activities(
@@ -316,14 +881,8 @@ mod test {
             destination: "file_4.txt",
         }],
         Config {
-            automatic_rename: false,
-            absolute: false,
-            editor: None,
-            ignore_hidden: false,
-            recursive: true,
             mkdir: false,
-            yes: false,
-            quiet: false,
+            ..Config::new()
         }
 
         // other/other_file_1 exists
@@ -335,14 +894,9 @@ mod test {
             destination: "other/other_file_2.txt",
         }],
         Config {
-            automatic_rename: true,
-            absolute: false,
-            editor: None,
-            ignore_hidden: false,
-            recursive: true,
+            on_conflict: ConflictStrategy::Rename,
             mkdir: false,
-            yes: false,
-            quiet: false,
+            ..Config::new()
         }
 
         test_batch_operations_move_many,
@@ -361,17 +915,483 @@ mod test {
             destination: "other/other_file_10.txt",
         }],
         Config {
-            automatic_rename: false,
-            absolute: false,
-            editor: None,
-            ignore_hidden: false,
-            recursive: true,
             mkdir: false,
-            yes: false,
-            quiet: false,
+            ..Config::new()
         }
     );
 
+    #[test]
+    fn test_case_insensitive_collision() {
+        use crate::exec::batch_operations;
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt"]);
+        let before_list = FileList::new_from_raw("a.txt\nb.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("a.txt\nA.TXT\n".to_owned()).unwrap();
+
+        let config = Config {
+            case_insensitive: true,
+            ..Config::new()
+        };
+
+        assert!(matches!(
+            batch_operations(&fs, &before_list, &after_list, &config),
+            Err(Error::CaseCollision {
+                line: 2,
+                other_line: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_target_exists_error() {
+        use crate::exec::batch_operations;
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt"]);
+        let before_list = FileList::new_from_raw("a.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("# note\nb.txt\n".to_owned()).unwrap();
+
+        match batch_operations(&fs, &before_list, &after_list, &Config::new()) {
+            Err(Error::TargetExists { line, from, to }) => {
+                assert_eq!(2, line);
+                assert_eq!(PathBuf::from("a.txt"), from);
+                assert_eq!(PathBuf::from("b.txt"), to);
+            }
+            other => panic!("Expected a TargetExists error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_target_vacated_by_other_case() {
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::case_insensitive();
+        for name in ["A.TXT", "c.txt"] {
+            fs.write(Path::new(name), name.as_bytes());
+        }
+        let before_list = FileList::new_from_raw("A.TXT\nc.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("b.txt\na.txt\n".to_owned()).unwrap();
+        let config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        // moving `A.TXT` away frees `a.txt` as well
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        perfom_operations(&fs, &outcome, &config).unwrap();
+        assert_eq!(
+            vec![
+                (PathBuf::from("a.txt"), b"c.txt".to_vec()),
+                (PathBuf::from("b.txt"), b"A.TXT".to_vec()),
+            ],
+            fs.files()
+        );
+    }
+
+    #[test]
+    fn test_case_only_rename() {
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let temp_path = tempdir.path().to_str().unwrap();
+        fs::File::create(format!("{}/Readme.md", temp_path)).expect("Error creating mock file...");
+
+        let before_list = FileList::new_from_raw(format!("{}/Readme.md\n", temp_path)).unwrap();
+        let after_list = FileList::new_from_raw(format!("{}/README.md\n", temp_path)).unwrap();
+
+        let config = Config {
+            case_insensitive: true,
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome =
+            batch_operations(&RealFileSystem, &before_list, &after_list, &config).unwrap();
+        assert!(outcome[0].case_only);
+
+        perfom_operations(&RealFileSystem, &outcome, &config).unwrap();
+        assert!(PathBuf::from(format!("{}/README.md", temp_path)).exists());
+    }
+
+    #[test]
+    fn test_conflict_backup_and_skip() {
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt", "c.txt"]);
+        let before_list = FileList::new_from_raw("a.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("b.txt\n".to_owned()).unwrap();
+
+        let mut config = Config {
+            on_conflict: ConflictStrategy::Skip,
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        assert_eq!(Some(ConflictStrategy::Skip), outcome[0].on_conflict);
+        perfom_operations(&fs, &outcome, &config).unwrap();
+        assert_eq!(Some(b"b.txt".to_vec()), fs.read(Path::new("b.txt")));
+
+        config.on_conflict = ConflictStrategy::Backup;
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        perfom_operations(&fs, &outcome, &config).unwrap();
+        assert_eq!(Some(b"a.txt".to_vec()), fs.read(Path::new("b.txt")));
+        assert_eq!(Some(b"b.txt".to_vec()), fs.read(Path::new("b.txt~")));
+    }
+
+    #[test]
+    fn test_swap_and_chain() {
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt", "c.txt", "d.txt"]);
+
+        // a and b are swapped, c moves onto d which moves to e
+        let before = "a.txt\nb.txt\nc.txt\nd.txt\n".to_owned();
+        let after = "b.txt\na.txt\nd.txt\ne.txt\n".to_owned();
+        let before_list = FileList::new_from_raw(before).unwrap();
+        let after_list = FileList::new_from_raw(after).unwrap();
+
+        let config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        perfom_operations(&fs, &outcome, &config).unwrap();
+
+        let files: Vec<(PathBuf, Vec<u8>)> = [("a", "b"), ("b", "a"), ("d", "c"), ("e", "d")]
+            .iter()
+            .map(|(name, content)| {
+                (
+                    PathBuf::from(format!("{}.txt", name)),
+                    format!("{}.txt", content).into_bytes(),
+                )
+            })
+            .collect();
+        assert_eq!(files, fs.files());
+        assert!(!fs.exists(outcome[0].destination.parent().unwrap()));
+    }
+
+    #[test]
+    fn test_taken_temp_name_is_not_replaced() {
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt"]);
+        let before_list = FileList::new_from_raw("a.txt\nb.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("b.txt\na.txt\n".to_owned()).unwrap();
+        let config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        // the dir of the temporary name is taken after planning
+        let temp = &outcome[0].destination;
+        fs.write(&temp.with_file_name("b.txt"), b"taken");
+
+        assert!(matches!(
+            perfom_operations(&fs, &outcome, &config),
+            Err(Error::Io { .. })
+        ));
+        assert_eq!(
+            Some(b"taken".to_vec()),
+            fs.read(&temp.with_file_name("b.txt"))
+        );
+        assert_eq!(Some(b"a.txt".to_vec()), fs.read(Path::new("a.txt")));
+    }
+
+    #[test]
+    fn test_column_changes() {
+        use crate::columns::Column;
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt"]);
+        let columns = [Column::Mode, Column::Mtime];
+        let before = "0644\t-\ta.txt\n0644\t-\tb.txt\n".to_owned();
+        let after = "0600\t-\tc.txt\n0755\t1970-01-02T00:00:00\tb.txt\n".to_owned();
+        let before_list = FileList::new_with_columns(before, &columns).unwrap();
+        let after_list = FileList::new_with_columns(after, &columns).unwrap();
+
+        let config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        assert_eq!(2, outcome.len());
+        assert!(outcome[0].is_rename());
+        assert!(!outcome[1].is_rename());
+        assert_eq!(2, outcome[1].changes.len());
+
+        perfom_operations(&fs, &outcome, &config).unwrap();
+        assert_eq!(Some(0o600), fs.attributes(Path::new("c.txt")).map(|a| a.0));
+        assert_eq!(Some(0o755), fs.attributes(Path::new("b.txt")).map(|a| a.0));
+        assert_ne!(
+            fs.metadata(Path::new("b.txt")).unwrap().mtime,
+            fs.metadata(Path::new("c.txt")).unwrap().mtime
+        );
+
+        let invalid = "0644\t-\ta.txt\n0999\t-\tb.txt\n".to_owned();
+        assert!(matches!(
+            FileList::new_with_columns(invalid, &columns),
+            Err(Error::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_delete_to_trash() {
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt", "c.txt"]);
+        let before_list = FileList::new_from_raw("a.txt\nb.txt\nc.txt\n".to_owned()).unwrap();
+        let mut config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        // the emptied line frees b.txt for a.txt, a removed line is refused
+        let after_list = FileList::new_from_raw("b.txt\n\nc.txt\n".to_owned()).unwrap();
+        let removed_list = FileList::new_from_raw("a.txt\nb.txt\n".to_owned()).unwrap();
+        assert!(matches!(
+            batch_operations(&fs, &before_list, &after_list, &config),
+            Err(Error::LineCount { .. })
+        ));
+
+        config.delete = true;
+        assert!(matches!(
+            batch_operations(&fs, &before_list, &removed_list, &config),
+            Err(Error::MissingLine { .. })
+        ));
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        assert!(outcome[0].delete);
+        assert_eq!(2, outcome[0].line);
+
+        perfom_operations(&fs, &outcome, &config).unwrap();
+        assert_eq!(Some(b"a.txt".to_vec()), fs.read(Path::new("b.txt")));
+        assert_eq!(
+            Some(b"b.txt".to_vec()),
+            fs.read(Path::new(".Trash/files/b.txt"))
+        );
+        assert!(!fs.exists(Path::new("a.txt")));
+    }
+
+    #[test]
+    fn test_delete_dir_with_renames_inside() {
+        use crate::exec::batch_operations;
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["dir/a.txt", "b.txt"]);
+        let before_list = FileList::new_from_raw("dir\ndir/a.txt\nb.txt\n".to_owned()).unwrap();
+        let config = Config {
+            yes: true,
+            quiet: true,
+            delete: true,
+            ..Config::new()
+        };
+
+        // a.txt would be trashed along with dir before its rename runs
+        let renamed_list = FileList::new_from_raw("\ndir/c.txt\nb.txt\n".to_owned()).unwrap();
+        let moved_in_list = FileList::new_from_raw("\ndir/a.txt\ndir/b.txt\n".to_owned()).unwrap();
+        for after_list in [renamed_list, moved_in_list] {
+            assert!(matches!(
+                batch_operations(&fs, &before_list, &after_list, &config),
+                Err(Error::InsideDeleted { other_line: 1, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_two_column_pairs_by_source() {
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt", "c.txt"]);
+        let before_list = FileList::new_two_column(
+            "a.txt\ta.txt\nb.txt\tb.txt\nc.txt\tc.txt\n".to_owned(),
+            &[],
+            None,
+        )
+        .unwrap();
+        let config = Config {
+            yes: true,
+            quiet: true,
+            delete: true,
+            ..Config::new()
+        };
+
+        // lines can be reordered, the left column tells what they rename
+        let after_list = FileList::new_two_column(
+            "c.txt\td.txt\nb.txt\t\na.txt\tb.txt\n".to_owned(),
+            &[],
+            Some(&before_list),
+        )
+        .unwrap();
+        assert!(matches!(
+            FileList::new_two_column(
+                "a.txt\tb.txt\nx.txt\tc.txt\n".to_owned(),
+                &[],
+                Some(&before_list)
+            ),
+            Err(Error::Parse { line: 2, .. })
+        ));
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        assert!(outcome[0].delete);
+        assert_eq!(2, outcome[0].line);
+
+        perfom_operations(&fs, &outcome, &config).unwrap();
+        assert_eq!(Some(b"a.txt".to_vec()), fs.read(Path::new("b.txt")));
+        assert_eq!(Some(b"c.txt".to_vec()), fs.read(Path::new("d.txt")));
+        assert_eq!(
+            Some(b"b.txt".to_vec()),
+            fs.read(Path::new(".Trash/files/b.txt"))
+        );
+        assert!(!fs.exists(Path::new("a.txt")));
+    }
+
+    #[test]
+    fn test_mkdir_on_execution() {
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt"]);
+        let before_list = FileList::new_from_raw("a.txt\nb.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("x/y/a.txt\nx/b.txt\n".to_owned()).unwrap();
+
+        let config = Config {
+            mkdir: true,
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        assert_eq!(
+            vec![PathBuf::from("x"), PathBuf::from("x/y")],
+            outcome[0].mkdirs
+        );
+        // nothing is created while planning
+        assert!(!fs.exists(Path::new("x")));
+
+        perfom_operations(&fs, &outcome, &config).unwrap();
+        assert!(fs.exists(Path::new("x/y/a.txt")));
+        assert!(fs.exists(Path::new("x/b.txt")));
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt"]);
+        let config = Config {
+            mkdir: false,
+            ..config
+        };
+        assert!(matches!(
+            batch_operations(&fs, &before_list, &after_list, &config),
+            Err(Error::MissingDir { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_changed_since_listing() {
+        use crate::exec::{batch_operations, list_files, perfom_operations};
+        use crate::filelist::FileList;
+
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let temp_path = tempdir.path().to_str().unwrap();
+        for name in ["a.txt", "b.txt"] {
+            fs::write(format!("{}/{}", temp_path, name), name)
+                .expect("Error creating mock file...");
+        }
+
+        let mut config = Config {
+            recursive: true,
+            mkdir: true,
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let before_list = list_files(vec![temp_path.to_owned()], &config).unwrap();
+        let after = format!("{0}/c.txt\n{0}/d.txt\n", temp_path);
+        let after_list = FileList::new_from_raw(after).unwrap();
+        let outcome =
+            batch_operations(&RealFileSystem, &before_list, &after_list, &config).unwrap();
+
+        // a.txt is replaced while the buffer is being edited
+        fs::remove_file(format!("{}/a.txt", temp_path)).unwrap();
+        fs::write(format!("{}/a.txt", temp_path), "other").unwrap();
+
+        // nothing runs once a check fails
+        assert!(matches!(
+            perfom_operations(&RealFileSystem, &outcome, &config),
+            Err(Error::SourceChanged { line: 1, .. })
+        ));
+        assert!(PathBuf::from(format!("{}/b.txt", temp_path)).exists());
+
+        config.skip_changed = true;
+        let outcome =
+            batch_operations(&RealFileSystem, &before_list, &after_list, &config).unwrap();
+        perfom_operations(&RealFileSystem, &outcome, &config).unwrap();
+
+        assert!(PathBuf::from(format!("{}/a.txt", temp_path)).exists());
+        assert!(!PathBuf::from(format!("{}/c.txt", temp_path)).exists());
+        assert!(PathBuf::from(format!("{}/d.txt", temp_path)).exists());
+    }
+
+    #[test]
+    fn test_changed_cycle_is_skipped_whole() {
+        use crate::exec::{batch_operations, perfom_operations};
+        use crate::filelist::FileList;
+
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt", "c.txt"]);
+        let mut before_list = FileList::new_from_raw("a.txt\nb.txt\nc.txt\n".to_owned()).unwrap();
+        for entry in before_list.list.iter_mut() {
+            entry.fingerprint = fs.metadata(&entry.source).ok();
+        }
+        let after_list = FileList::new_from_raw("b.txt\na.txt\nd.txt\n".to_owned()).unwrap();
+        let mut config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        fs.write(Path::new("b.txt"), b"changed");
+
+        assert!(matches!(
+            perfom_operations(&fs, &outcome, &config),
+            Err(Error::SourceChanged { line: 2, .. })
+        ));
+        assert_eq!(3, fs.files().len());
+        assert!(fs.exists(Path::new("c.txt")));
+
+        // the swap stays as it is, without a file left at a temporary name
+        config.skip_changed = true;
+        perfom_operations(&fs, &outcome, &config).unwrap();
+        let files: Vec<PathBuf> = fs.files().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(
+            vec![
+                PathBuf::from("a.txt"),
+                PathBuf::from("b.txt"),
+                PathBuf::from("d.txt")
+            ],
+            files
+        );
+    }
+
     #[test]
     fn test_list_files_recursive() {
         use crate::exec::list_files;
@@ -406,7 +1426,10 @@ mod test {
             fs::File::create(file).expect("Error creating mock file...");
         }
 
-        let mock_config = Config::new();
+        let mock_config = Config {
+            recursive: true,
+            ..Config::new()
+        };
 
         if let Ok(result) = list_files(vec![temp_path.to_owned()], &mock_config) {
             let first = result.get_by_index(0).unwrap_or_else(|| {
@@ -1,82 +1,379 @@
-use std::collections::BTreeSet;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{fs, io};
 
-use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::columns::{Attributes, Column, SEPARATOR};
+use crate::errors::Error;
+
+/// Lines of the buffer starting with this are ignored.
+pub const COMMENT: char = '#';
+
+/// What a file looked like when it was listed, to notice later changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub dev: u64,
+    pub ino: u64,
+    pub is_dir: bool,
+    pub size: u64,
+    pub mtime: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    /// Read the fingerprint of a path, without following symlinks
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::symlink_metadata(path)?;
+
+        #[cfg(unix)]
+        let (dev, ino) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.dev(), metadata.ino())
+        };
+        #[cfg(not(unix))]
+        let (dev, ino) = (0, 0);
+
+        Ok(Self {
+            dev,
+            ino,
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            mtime: metadata.modified().ok(),
+        })
+    }
+
+    /// Wether `current` is still the same file, directories only have to
+    /// keep their identity as their content is allowed to change.
+    pub fn matches(&self, current: &Fingerprint) -> bool {
+        let same_file = self.dev == current.dev && self.ino == current.ino;
+        if self.is_dir || current.is_dir {
+            return same_file && self.is_dir == current.is_dir;
+        }
+
+        same_file && self.size == current.size && self.mtime == current.mtime
+    }
+
+    /// Wether `current` still looks like the same content, by size and mtime
+    /// only, as the identity of a file changes on another machine or checkout.
+    pub fn matches_content(&self, current: &Fingerprint) -> bool {
+        if self.is_dir || current.is_dir {
+            return self.is_dir == current.is_dir;
+        }
+
+        self.size == current.size && self.mtime == current.mtime
+    }
+}
 
 #[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Clone)]
 pub struct FileDirPosition {
     pub source: PathBuf,
     pub position: usize,
+    /// Line of the buffer the path has been read from, starting at 1.
+    pub line: usize,
+    /// State of the file when it was listed, if known.
+    pub fingerprint: Option<Fingerprint>,
+    /// Values of the extra columns of the line.
+    pub attributes: Attributes,
+    /// Left column of a two-column buffer, the path as listed.
+    pub origin: Option<PathBuf>,
 }
 
-#[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Clone)]
+/// Ordered list of paths, indexed both by path and by buffer position so that
+/// lookups stay O(1) on very large trees.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
 pub struct FileList {
-    pub list: BTreeSet<FileDirPosition>,
+    pub list: Vec<FileDirPosition>,
     pub raw: String,
+    /// Extra columns written before each path.
+    pub columns: Vec<Column>,
+    /// Lines are `source<TAB>destination`, paired by source not position.
+    pub two_column: bool,
+    by_path: HashMap<PathBuf, usize>,
+    by_position: HashMap<usize, usize>,
+    by_origin: HashMap<PathBuf, usize>,
 }
 
 impl FileList {
     pub fn new() -> Self {
-        Self {
-            list: BTreeSet::new(),
-            raw: String::new(),
-        }
+        Self::default()
+    }
+
+    /// Parse an edited buffer, comment lines do not count as positions.
+    pub fn new_from_raw(raw: String) -> Result<Self, Error> {
+        Self::new_with_columns(raw, &[])
+    }
+
+    /// Parse an edited buffer whose lines start with `columns`.
+    pub fn new_with_columns(raw: String, columns: &[Column]) -> Result<Self, Error> {
+        Self::parse(raw, columns, false, None)
+    }
+
+    /// Parse an edited two-column buffer, each line being the listed path
+    /// and its destination, after the `columns`. An empty destination
+    /// stands for a deletion. The left column has to be a path of
+    /// `original`, unless the buffer is the listing itself.
+    pub fn new_two_column(
+        raw: String,
+        columns: &[Column],
+        original: Option<&FileList>,
+    ) -> Result<Self, Error> {
+        Self::parse(raw, columns, true, original)
     }
 
-    pub fn new_from_raw(raw: String) -> Result<Self, anyhow::Error> {
-        let mut new_self = Self::new();
-        new_self.raw = raw.clone();
-        for (i, path) in raw.split('\n').enumerate() {
-            if path.is_empty() {
+    fn parse(
+        raw: String,
+        columns: &[Column],
+        two_column: bool,
+        original: Option<&FileList>,
+    ) -> Result<Self, Error> {
+        let mut new_self = Self {
+            columns: columns.to_vec(),
+            two_column,
+            ..Self::new()
+        };
+        let mut problems = Vec::new();
+        let mut position = 0;
+        for (i, line) in raw.split('\n').enumerate() {
+            if line.starts_with(COMMENT) {
                 continue;
             }
 
-            let outcome = new_self.insert(PathBuf::from(path), i);
-            if !outcome {
-                bail!("Duplicate path {:?}", path)
+            position += 1;
+            if line.is_empty() {
+                continue;
+            }
+
+            let (attributes, path) = match Attributes::parse(line, columns) {
+                Ok(parsed) => parsed,
+                Err(message) => {
+                    problems.push(Error::Parse {
+                        line: i + 1,
+                        message,
+                    });
+                    continue;
+                }
+            };
+
+            let (origin, path) = match (two_column, path.split_once(SEPARATOR)) {
+                (false, _) => (None, path),
+                (true, Some((origin, path))) => (Some(PathBuf::from(origin)), path),
+                (true, None) => {
+                    problems.push(Error::Parse {
+                        line: i + 1,
+                        message: "expected `source<TAB>destination`".to_owned(),
+                    });
+                    continue;
+                }
+            };
+
+            if let (Some(original), Some(origin)) = (original, &origin) {
+                if original.get_by_file(origin).is_none() {
+                    problems.push(Error::Parse {
+                        line: i + 1,
+                        message: format!(
+                            "{:?} was not listed, the left column must stay as it is",
+                            origin
+                        ),
+                    });
+                    continue;
+                }
+            }
+
+            if let Some(first) = origin
+                .as_ref()
+                .and_then(|origin| new_self.get_by_origin(origin))
+            {
+                problems.push(Error::Parse {
+                    line: i + 1,
+                    message: format!(
+                        "{:?} is already the source of line {}",
+                        first.origin.as_ref().unwrap(),
+                        first.line
+                    ),
+                });
+                continue;
+            }
+
+            let value = PathBuf::from(path);
+            if let Some(first) = new_self.get_by_file(&value) {
+                problems.push(Error::DuplicatePath {
+                    line: i + 1,
+                    first_line: first.line,
+                    path: value,
+                });
+                continue;
+            }
+
+            new_self.insert_line(value, position - 1, i + 1);
+            let index = new_self.list.len() - 1;
+            if let Some(origin) = &origin {
+                new_self.by_origin.insert(origin.clone(), index);
             }
+            let entry = &mut new_self.list[index];
+            entry.attributes = attributes;
+            entry.origin = origin;
         }
 
+        Error::from_list(problems)?;
+        new_self.raw = raw;
+
         Ok(new_self)
     }
 
     pub fn insert(&mut self, value: PathBuf, position: usize) -> bool {
-        let found = self.list.iter().find(|file| file.source == value);
-        if found.is_some() {
+        self.insert_line(value, position, position + 1)
+    }
+
+    fn insert_line(&mut self, value: PathBuf, position: usize, line: usize) -> bool {
+        if self.by_path.contains_key(&value) {
             return false;
         }
 
-        self.list.insert(FileDirPosition {
+        // emptied destinations of a two-column buffer are not paths
+        let index = self.list.len();
+        if !value.as_os_str().is_empty() {
+            self.by_path.insert(value.clone(), index);
+        }
+        self.by_position.entry(position).or_insert(index);
+        self.list.push(FileDirPosition {
             source: value,
             position,
-        })
+            line,
+            fingerprint: None,
+            attributes: Attributes::default(),
+            origin: None,
+        });
+
+        true
     }
 
+    /// Sort the list by path and assign each entry its final position.
     pub fn enumerate(&mut self) {
-        let mut new_self = Self::new();
-        for (i, path) in self.list.iter().enumerate() {
-            new_self.insert(path.source.to_owned(), i);
+        let mut list = std::mem::take(&mut self.list);
+        list.sort_unstable_by(|a, b| a.source.cmp(&b.source));
 
-            let output_path = path.source.display().to_string() + "\n";
-            new_self.raw += &output_path;
+        let mut new_self = Self {
+            columns: std::mem::take(&mut self.columns),
+            two_column: self.two_column,
+            ..Self::new()
+        };
+        for (i, path) in list.into_iter().enumerate() {
+            let source = escape_comment(path.source);
+            new_self.raw += &new_self.line_for(&path.attributes, &source, &source);
+
+            if new_self.insert(source.clone(), i) {
+                let index = new_self.list.len() - 1;
+                if new_self.two_column {
+                    new_self.by_origin.insert(source.clone(), index);
+                    new_self.list[index].origin = Some(source);
+                }
+                let entry = &mut new_self.list[index];
+                entry.fingerprint = path.fingerprint;
+                entry.attributes = path.attributes;
+            }
         }
 
         *self = new_self
     }
 
+    /// Listing of the same entries in the same order, each at the path
+    /// `locate` gives it. Entries without a path are left out.
+    pub fn relisted(&self, locate: impl Fn(&FileDirPosition) -> Option<PathBuf>) -> Self {
+        let mut new_self = Self {
+            columns: self.columns.clone(),
+            two_column: self.two_column,
+            ..Self::new()
+        };
+        for entry in self.list.iter() {
+            let source = match locate(entry) {
+                Some(source) => escape_comment(source),
+                None => continue,
+            };
+            new_self.raw += &new_self.line_for(&entry.attributes, &source, &source);
+
+            let position = new_self.list.len();
+            if new_self.insert(source.clone(), position) {
+                if new_self.two_column {
+                    new_self.by_origin.insert(source.clone(), position);
+                    new_self.list[position].origin = Some(source);
+                }
+                new_self.list[position].attributes = entry.attributes.clone();
+            }
+        }
+
+        new_self
+    }
+
+    /// Buffer listing every entry in order, each renamed by `rename`, as if
+    /// the lines had been edited that way.
+    pub fn buffer_with(&self, rename: impl Fn(&Path) -> PathBuf) -> String {
+        let mut raw = String::new();
+        for entry in self.list.iter() {
+            let destination = escape_comment(rename(&entry.source));
+            raw += &self.line_for(&entry.attributes, &entry.source, &destination);
+        }
+
+        raw
+    }
+
+    fn line_for(&self, attributes: &Attributes, source: &Path, destination: &Path) -> String {
+        let mut line = attributes.format(&self.columns);
+        if self.two_column {
+            line += &source.display().to_string();
+            line.push(SEPARATOR);
+        }
+        line += &destination.display().to_string();
+        line.push('\n');
+
+        line
+    }
+
+    /// Line of each empty position of the buffer, starting at 1. The end of
+    /// the last line does not start an empty one.
+    pub fn blank_lines(&self) -> HashMap<usize, usize> {
+        let mut blank = HashMap::new();
+        let mut position = 0;
+        for (i, line) in self.raw.lines().enumerate() {
+            if line.starts_with(COMMENT) {
+                continue;
+            }
+
+            if line.is_empty() {
+                blank.insert(position, i + 1);
+            }
+            position += 1;
+        }
+
+        blank
+    }
+
     pub fn get_by_index(&self, index: usize) -> Option<&FileDirPosition> {
-        self.list.iter().find(|file| file.position == index)
+        self.by_position.get(&index).map(|&i| &self.list[i])
     }
 
     pub fn get_by_file(&self, path: &Path) -> Option<&FileDirPosition> {
-        self.list
-            .iter()
-            .find(|file| file.source.to_str() == path.to_str())
+        self.by_path.get(path).map(|&i| &self.list[i])
+    }
+
+    /// Entry of a two-column buffer by its left column.
+    pub fn get_by_origin(&self, origin: &Path) -> Option<&FileDirPosition> {
+        self.by_origin.get(origin).map(|&i| &self.list[i])
     }
 }
 
+/// Prefix paths that would be read back as a comment with `./`.
+fn escape_comment(path: PathBuf) -> PathBuf {
+    if path.to_string_lossy().starts_with(COMMENT) {
+        return Path::new(".").join(path);
+    }
+
+    path
+}
+
 #[cfg(test)]
 mod test {
+    use crate::columns::Column;
     use crate::filelist::FileList;
 
     #[test]
@@ -96,6 +393,96 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_enumerate_sorts_by_path() {
+        use std::path::PathBuf;
+        let mut list = FileList::new();
+        list.insert(PathBuf::from("tmp/b.txt"), 0);
+        list.insert(PathBuf::from("tmp/c.txt"), 0);
+        list.insert(PathBuf::from("tmp/a.txt"), 0);
+        list.enumerate();
+
+        assert_eq!("tmp/a.txt\ntmp/b.txt\ntmp/c.txt\n", list.raw);
+
+        list.columns = vec![Column::Mode];
+        list.list[1].attributes.mode = Some(0o755);
+        list.enumerate();
+
+        assert_eq!("-\ttmp/a.txt\n0755\ttmp/b.txt\n-\ttmp/c.txt\n", list.raw);
+        assert_eq!(
+            Some(2),
+            list.get_by_file(&PathBuf::from("tmp/c.txt"))
+                .map(|file| file.position)
+        );
+    }
+
+    #[test]
+    fn test_two_column() {
+        use std::path::{Path, PathBuf};
+        let mut list = FileList::new();
+        list.two_column = true;
+        list.insert(PathBuf::from("tmp/b.txt"), 0);
+        list.insert(PathBuf::from("tmp/a.txt"), 0);
+        list.enumerate();
+
+        assert_eq!("tmp/a.txt\ttmp/a.txt\ntmp/b.txt\ttmp/b.txt\n", list.raw);
+        assert_eq!(
+            list,
+            FileList::new_two_column(list.raw.clone(), &[], None).unwrap()
+        );
+
+        let edited = "tmp/b.txt\ttmp/c.txt\ntmp/a.txt\t\n";
+        let result = FileList::new_two_column(edited.to_owned(), &[], Some(&list)).unwrap();
+        let entry = result.get_by_origin(Path::new("tmp/a.txt")).unwrap();
+        assert_eq!((PathBuf::new(), 2), (entry.source.clone(), entry.line));
+
+        let err =
+            FileList::new_two_column("tmp/a.txt\nb\tc\nb\td\n".to_owned(), &[], None).unwrap_err();
+        assert_eq!(
+            "line 1: expected `source<TAB>destination`\nline 3: \"b\" is already the source of line 2",
+            err.to_string()
+        );
+
+        let err = FileList::new_two_column("tmp/x.txt\ttmp/c.txt\n".to_owned(), &[], Some(&list))
+            .unwrap_err();
+        assert!(matches!(err, crate::errors::Error::Parse { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        use std::path::PathBuf;
+        let raw = "# -*- mode: rnr-buf -*-\ntmp/file_1.txt\n# note\ntmp/file_2.txt\n";
+        let result = FileList::new_from_raw(raw.to_owned()).expect("Failed to parse buffer");
+
+        assert_eq!(2, result.list.len());
+        assert_eq!(
+            PathBuf::from("tmp/file_2.txt"),
+            result.get_by_index(1).expect("Missing second item").source
+        );
+    }
+
+    #[test]
+    fn test_enumerate_escapes_comments() {
+        use std::path::PathBuf;
+        let mut list = FileList::new();
+        list.insert(PathBuf::from("#notes.txt"), 0);
+        list.enumerate();
+
+        assert_eq!("./#notes.txt\n", list.raw);
+        assert_eq!(list, FileList::new_from_raw(list.raw.clone()).unwrap());
+    }
+
+    #[test]
+    fn test_duplicates_report_lines() {
+        let raw = "tmp/a.txt\n# note\ntmp/b.txt\ntmp/a.txt\ntmp/b.txt\n";
+        let err = FileList::new_from_raw(raw.to_owned()).unwrap_err();
+
+        assert_eq!(
+            "line 4: duplicate path \"tmp/a.txt\", already on line 1\nline 5: duplicate path \"tmp/b.txt\", already on line 3",
+            err.to_string()
+        );
+    }
+
     #[test]
     #[should_panic]
     fn test_duplicate_file() {
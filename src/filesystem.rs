@@ -1,27 +1,243 @@
-use std::fs;
-use std::path::{Path, PathBuf};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs, io, process};
+
+use anyhow::bail;
+use walkdir::WalkDir;
+
+use crate::filelist::Fingerprint;
+use crate::template::{format_number, Segment, Template};
+
+/// Filesystem operations used by the planner and the executor, so that they
+/// can run against the disk or against an in-memory tree.
+pub trait FileSystem {
+    fn exists(&self, path: &Path) -> bool;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// Identity, size and mtime of a path, without following symlinks.
+    fn metadata(&self, path: &Path) -> io::Result<Fingerprint>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove an empty dir.
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Move a file or dir to the trash, returning where it went.
+    fn trash(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Set the permission bits, as `chmod`.
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()>;
+
+    /// Set the owner and group, as `chown`.
+    fn set_owner(&self, path: &Path, uid: u32, gid: u32) -> io::Result<()>;
+
+    fn set_mtime(&self, path: &Path, mtime: SystemTime) -> io::Result<()>;
+
+    /// Wether names only differing by case are the same file in `dir`.
+    fn is_case_insensitive(&self, dir: &Path) -> bool;
+
+    /// Wether a tracked file, or one below a dir, differs from the index of
+    /// its version control.
+    fn has_unstaged_changes(&self, _path: &Path) -> bool {
+        false
+    }
+
+    /// Target of a symlink, as written in it.
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Create a symlink at `link` pointing at `target`.
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()>;
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+
+    /// Replace the content of an existing file.
+    fn rewrite(&self, path: &Path, content: &[u8]) -> io::Result<()>;
+
+    /// Entries below `root`, without following symlinks nor entering `.git`
+    /// dirs. Entries that cannot be read are left out.
+    fn walk(&self, root: &Path) -> Vec<(PathBuf, EntryKind)>;
+}
+
+/// What a walked entry is, symlinks are not followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// The actual filesystem.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Fingerprint> {
+        Fingerprint::of(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        fs::copy(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        crate::trash::trash(path)
+    }
+
+    #[cfg(unix)]
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn set_mode(&self, _path: &Path, _mode: u32) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    #[cfg(unix)]
+    fn set_owner(&self, path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+        std::os::unix::fs::lchown(path, Some(uid), Some(gid))
+    }
+
+    #[cfg(not(unix))]
+    fn set_owner(&self, _path: &Path, _uid: u32, _gid: u32) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn set_mtime(&self, path: &Path, mtime: SystemTime) -> io::Result<()> {
+        fs::File::open(path)?.set_modified(mtime)
+    }
+
+    fn is_case_insensitive(&self, dir: &Path) -> bool {
+        is_case_insensitive(dir)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    #[cfg(unix)]
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        std::os::unix::fs::symlink(target, link)
+    }
+
+    #[cfg(windows)]
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        if link
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(target)
+            .is_dir()
+        {
+            std::os::windows::fs::symlink_dir(target, link)
+        } else {
+            std::os::windows::fs::symlink_file(target, link)
+        }
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn rewrite(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        fs::write(path, content)
+    }
+
+    fn walk(&self, root: &Path) -> Vec<(PathBuf, EntryKind)> {
+        WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .flatten()
+            .map(|entry| {
+                let kind = if entry.path_is_symlink() {
+                    EntryKind::Symlink
+                } else if entry.file_type().is_dir() {
+                    EntryKind::Dir
+                } else {
+                    EntryKind::File
+                };
+
+                (entry.into_path(), kind)
+            })
+            .collect()
+    }
+}
 
 /// Get the last part of the path
 pub fn get_last_component(path: &Path) -> &str {
-    match path.iter().last() {
+    match path.iter().next_back() {
         Some(component) => component.to_str().unwrap_or(""),
         None => "",
     }
 }
 
-/// Wether the dirs in a file exist or not
-pub fn all_dirs_exist(path: &Path) -> bool {
-    let mut current_path = PathBuf::new();
-
+/// Absolute version of `path`, with `.` and `..` resolved without touching
+/// the filesystem
+pub fn absolute_path(path: &Path) -> PathBuf {
+    let mut absolute = match env::current_dir() {
+        Ok(current) => current,
+        Err(_) => PathBuf::from("/"),
+    };
     for component in path.components() {
-        current_path.push(component);
-
-        if current_path.extension().is_none() && !current_path.exists() {
-            return false;
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            other => absolute.push(other),
         }
     }
 
-    true
+    absolute
+}
+
+/// Directories above `path` that do not exist yet, from the outermost one
+pub fn missing_dirs(fs: &dyn FileSystem, path: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = path
+        .ancestors()
+        .skip(1)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .take_while(|dir| !fs.is_dir(dir))
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+
+    missing
 }
 
 /// Wether path contains a hidden component
@@ -37,83 +253,262 @@ pub fn has_hidden(path: &Path) -> bool {
     false
 }
 
-pub fn create_all_dirs(path: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
-    let mut current_path = PathBuf::new();
-    let mut created_dirs: Vec<PathBuf> = Vec::new();
-
-    for component in path.components() {
-        current_path.push(component);
-        if current_path.extension().is_none() && !current_path.exists() {
-            fs::create_dir(&current_path)?;
-            created_dirs.push(current_path.clone());
-        }
+/// Directory a path lives in, `.` for bare file names
+pub fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
     }
+}
 
-    Ok(created_dirs)
+/// Path used to compare names on a case-insensitive filesystem
+pub fn case_fold(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
 }
 
-/// Given a pathbuf generate the next in line automatic
-pub fn file_autonamer(path: &Path) -> PathBuf {
-    let mut new_file_path = path.to_path_buf();
+/// Wether the two paths only differ by case
+pub fn is_case_only_change(source: &Path, destination: &Path) -> bool {
+    source != destination && case_fold(source) == case_fold(destination)
+}
+
+/// Wether a directory is case-insensitive: a cased entry of the nearest
+/// existing ancestor is looked up again with its case swapped. Nothing is
+/// created, a dir without any cased entry is taken as case-sensitive.
+pub fn is_case_insensitive(dir: &Path) -> bool {
+    let existing = dir
+        .ancestors()
+        .map(|ancestor| match ancestor.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => ancestor,
+        })
+        .find(|ancestor| ancestor.is_dir());
+    let entries = match existing.map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return false,
+    };
 
-    while new_file_path.exists() {
-        // If it does, add a number to the filename and try again
-        let file_name = path.file_name().unwrap().to_string_lossy();
-        let (mut name, extension) = match file_name.rsplit_once('.') {
-            Some((name, ext)) => (name, ext),
-            None => (file_name.as_ref(), ""),
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => continue,
         };
+        let upper = name.to_uppercase();
+        let swapped = if upper != name {
+            upper
+        } else {
+            name.to_lowercase()
+        };
+        if swapped == name {
+            continue;
+        }
 
-        let (name_split, after_split) = match name.rsplit_once('_') {
-            Some((name, number)) => (name, number),
-            None => (name, ""),
+        // a different file under the swapped name means case matters too
+        let path = entry.path();
+        return match (
+            Fingerprint::of(&path),
+            Fingerprint::of(&path.with_file_name(swapped)),
+        ) {
+            (Ok(entry), Ok(other)) => entry == other,
+            _ => false,
         };
+    }
+
+    false
+}
+
+/// Prefix of the dirs holding an entry moved out of the way.
+const TEMP_PREFIX: &str = ".rnr-buf-";
+
+/// How many random names are drawn before giving up on a free one.
+const TEMP_ATTEMPTS: usize = 64;
+
+/// Random characters for temporary names.
+fn random_suffix() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(process::id());
 
-        // Find the next available number
-        let mut number = 1;
+    format!("{:016x}", hasher.finish())[..8].to_owned()
+}
+
+/// Temporary path for `path` while it is moved out of the way: its own name
+/// inside a dir next to it, `.rnr-buf-` followed by random characters, that
+/// does not exist yet. The dir is created by [`reserve_temp`] right before
+/// the move.
+pub fn temp_name(fs: &dyn FileSystem, path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or(path.as_os_str());
 
-        if !after_split.is_empty() {
-            // Parse the string into an integer
-            if let Ok(num) = after_split.parse::<i32>() {
-                number = num;
-                name = name_split;
-            };
+    let mut dir = PathBuf::new();
+    for _ in 0..TEMP_ATTEMPTS {
+        dir = path.with_file_name(format!("{}{}", TEMP_PREFIX, random_suffix()));
+        if !fs.exists(&dir) {
+            break;
         }
+    }
 
-        loop {
-            let mut numbered_name = format!("{}_{}", name, number);
-            if !extension.is_empty() {
-                numbered_name = format!("{}.{}", numbered_name, extension);
-            }
+    // a taken dir makes `reserve_temp` fail rather than reuse it
+    dir.join(name)
+}
+
+/// Create the dir of a path given by [`temp_name`], failing if something
+/// took it meanwhile, so that the move cannot replace anything.
+pub fn reserve_temp(fs: &dyn FileSystem, temp: &Path) -> io::Result<()> {
+    fs.create_dir(parent_dir(temp))
+}
+
+/// Remove the dir of a path given by [`temp_name`] once its entry moved on.
+pub fn release_temp(fs: &dyn FileSystem, temp: &Path) -> io::Result<()> {
+    fs.remove_dir(parent_dir(temp))
+}
+
+/// Rename going through a temporary name, some case-insensitive filesystems
+/// ignore renames that only change the case.
+pub fn rename_via_temp(fs: &dyn FileSystem, source: &Path, destination: &Path) -> io::Result<()> {
+    let temp = temp_name(fs, source);
+
+    reserve_temp(fs, &temp)?;
+    fs.rename(source, &temp)?;
+    fs.rename(&temp, destination)?;
+    release_temp(fs, &temp)
+}
+
+/// Where to move an existing file out of the way, `name~` or a timestamped
+/// `name.<seconds>~` when that is taken too.
+pub fn backup_name(fs: &dyn FileSystem, path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push("~");
+    let backup = PathBuf::from(backup);
+    if !fs.exists(&backup) {
+        return backup;
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{}~", timestamp));
+
+    PathBuf::from(backup)
+}
+
+/// Split a file name into stem and extension, keeping `.tar.*` extensions whole
+pub fn split_extension(file_name: &str) -> (&str, &str) {
+    let dot = match file_name.rfind('.') {
+        Some(0) | None => return (file_name, ""),
+        Some(dot) => dot,
+    };
 
-            new_file_path.set_file_name(&numbered_name);
-            if !new_file_path.exists() {
-                break;
+    let stem = &file_name[..dot];
+    if let Some(inner) = stem.rfind('.') {
+        if inner > 0 && stem[inner + 1..].eq_ignore_ascii_case("tar") {
+            return (&file_name[..inner], &file_name[inner..]);
+        }
+    }
+
+    (stem, &file_name[dot..])
+}
+
+/// Parse a `--autoname-template`, it needs a `{n}` to count with and its
+/// number format is checked here so that rendering cannot fail.
+pub fn autoname_template(template: &str) -> Result<Template, anyhow::Error> {
+    let template = Template::parse(template)?;
+    template.check_placeholders(&["stem", "n", "ext"])?;
+    if !template.placeholders().any(|name| name == "n") {
+        bail!("The autoname template needs a `{{n}}` placeholder");
+    }
+
+    for segment in &template.segments {
+        match segment {
+            Segment::Placeholder { name, arg } if name == "n" => {
+                format_number(0, arg.as_deref())?;
             }
+            Segment::Placeholder {
+                name,
+                arg: Some(arg),
+            } => bail!("`{{{}}}` takes no argument, got `{}`", name, arg),
+            _ => {}
+        }
+    }
 
-            number += 1;
+    Ok(template)
+}
+
+/// Given a pathbuf generate the next in line automatic name following the
+/// template, continuing the counter when the name already follows it. Only
+/// numbers written in the format of `{n}` count, so `report_2024.txt` is not
+/// taken for a numbered name. Names in `reserved` are taken by other renames
+/// of the same plan.
+pub fn file_autonamer(
+    fs: &dyn FileSystem,
+    path: &Path,
+    template: &Template,
+    reserved: &HashSet<PathBuf>,
+) -> Result<PathBuf, anyhow::Error> {
+    let taken = |path: &Path| fs.exists(path) || reserved.contains(path);
+    if !taken(path) {
+        return Ok(path.to_path_buf());
+    }
+
+    let file_name = get_last_component(path);
+    let (stem, ext) = split_extension(file_name);
+    let mut stem = stem.to_owned();
+    let mut number = 1;
+
+    let fixed = HashMap::from([("ext", ext)]);
+    if let Some(captures) = template.capture(file_name, &fixed) {
+        if let (Some(name), Some(Ok(counter))) = (
+            captures.get("stem"),
+            captures.get("n").map(|n| n.trim().parse::<u64>()),
+        ) {
+            stem = name.clone();
+            number = counter;
         }
     }
 
-    new_file_path
+    let mut new_file_path = path.to_path_buf();
+    loop {
+        let numbered_name = template.render(|name, arg| match name {
+            "stem" => Ok(stem.clone()),
+            "ext" => Ok(ext.to_owned()),
+            _ => format_number(number, arg),
+        })?;
+
+        new_file_path.set_file_name(&numbered_name);
+        if !taken(&new_file_path) {
+            break;
+        }
+
+        number += 1;
+    }
+
+    Ok(new_file_path)
 }
 
 #[cfg(test)]
 mod test {
-    use std::fs::{remove_file, File};
-    use std::path::PathBuf;
+    use std::collections::HashSet;
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
 
-    use crate::filesystem::file_autonamer;
+    use crate::config::DEFAULT_AUTONAME_TEMPLATE;
+    use crate::filesystem::{
+        autoname_template, file_autonamer, is_case_insensitive, is_case_only_change, missing_dirs,
+        rename_via_temp, split_extension, RealFileSystem,
+    };
+    use crate::memory::MemoryFileSystem;
 
     macro_rules! func_assert_rename {
-        ($($test_name:ident, $existing_file:expr, $func_name:ident ($value:expr) => $expected:expr)*) => {
+        ($($test_name:ident, $existing_file:expr, $func_name:ident ($value:expr, $template:expr) => $expected:expr)*) => {
             $(
                 #[test]
                 fn $test_name() {
-                    let _ = File::create(&$existing_file).expect("Failed to create file");
+                    let fs = MemoryFileSystem::with_files([$existing_file]);
 
-                    let output = $func_name(PathBuf::from(&$value).as_path());
-                    drop(remove_file(&$existing_file));
+                    let template = autoname_template($template).expect("Invalid template");
+                    let output = $func_name(&fs, PathBuf::from(&$value).as_path(), &template, &HashSet::new())
+                        .expect("Failed to autoname");
 
                     assert_eq!(output.to_str().unwrap_or(""), $expected);
                 }
@@ -121,12 +516,84 @@ mod test {
         };
     }
 
+    #[test]
+    fn test_case_only_change() {
+        assert!(is_case_only_change(
+            &PathBuf::from("docs/Readme.md"),
+            &PathBuf::from("docs/README.md")
+        ));
+        assert!(!is_case_only_change(
+            &PathBuf::from("Readme.md"),
+            &PathBuf::from("Readme.md")
+        ));
+        assert!(!is_case_only_change(
+            &PathBuf::from("Readme.md"),
+            &PathBuf::from("Readme.txt")
+        ));
+    }
+
+    #[test]
+    fn test_rename_via_temp() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let source = tempdir.path().join("Readme.md");
+        let destination = tempdir.path().join("README.md");
+        File::create(&source).expect("Failed to create file");
+
+        rename_via_temp(&RealFileSystem, &source, &destination).expect("Failed to rename");
+
+        assert!(destination.exists());
+        assert_eq!(1, tempdir.path().read_dir().unwrap().count());
+    }
+
+    #[test]
+    fn test_case_probe_creates_nothing() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        File::create(tempdir.path().join("Readme.md")).expect("Failed to create file");
+
+        is_case_insensitive(&tempdir.path().join("missing/dir"));
+
+        assert_eq!(1, tempdir.path().read_dir().unwrap().count());
+    }
+
+    #[test]
+    fn test_missing_dirs() {
+        let fs = MemoryFileSystem::with_files(["a/b.txt"]);
+
+        assert_eq!(
+            vec![PathBuf::from("a/c"), PathBuf::from("a/c/d")],
+            missing_dirs(&fs, Path::new("a/c/d/e.txt"))
+        );
+        assert!(missing_dirs(&fs, Path::new("a/e.txt")).is_empty());
+        assert!(missing_dirs(&fs, Path::new("e.txt")).is_empty());
+    }
+
+    #[test]
+    fn test_split_extension() {
+        assert_eq!(("archive", ".tar.gz"), split_extension("archive.tar.gz"));
+        assert_eq!(("report_2024", ".txt"), split_extension("report_2024.txt"));
+        assert_eq!((".bashrc", ""), split_extension(".bashrc"));
+        assert_eq!(("file", ""), split_extension("file"));
+    }
+
+    #[test]
+    fn test_autoname_template_needs_counter() {
+        assert!(autoname_template("{stem}{ext}").is_err());
+        assert!(autoname_template("{stem}_{size}{ext}").is_err());
+        assert!(autoname_template("{stem}{n:x}{ext}").is_err());
+        assert!(autoname_template("{stem:x}{n}{ext}").is_err());
+    }
+
     func_assert_rename!(
-        test_rename_no_match, "other.txt", file_autonamer ("file_1.txt") => "file_1.txt"
-        test_rename_match, "file_1.txt", file_autonamer ("file_1.txt") => "file_2.txt"
-        test_rename_compound, "file.txt", file_autonamer ("file.txt") => "file_1.txt"
-        test_rename_utf8, "👨.txt", file_autonamer ("👨.txt") => "👨_1.txt"
-        test_rename_harder_match, "_1file9.txt", file_autonamer ("_1file9.txt") => "_1file9_1.txt"
-        test_rename_no_extension, "file", file_autonamer ("file") => "file_1"
+        test_rename_no_match, "other.txt", file_autonamer ("file_1.txt", DEFAULT_AUTONAME_TEMPLATE) => "file_1.txt"
+        test_rename_match, "file_1.txt", file_autonamer ("file_1.txt", DEFAULT_AUTONAME_TEMPLATE) => "file_2.txt"
+        test_rename_year, "report_2024.txt", file_autonamer ("report_2024.txt", DEFAULT_AUTONAME_TEMPLATE) => "report_2024_1.txt"
+        test_rename_compound, "file.txt", file_autonamer ("file.txt", DEFAULT_AUTONAME_TEMPLATE) => "file_1.txt"
+        test_rename_utf8, "👨.txt", file_autonamer ("👨.txt", DEFAULT_AUTONAME_TEMPLATE) => "👨_1.txt"
+        test_rename_harder_match, "_1file9.txt", file_autonamer ("_1file9.txt", DEFAULT_AUTONAME_TEMPLATE) => "_1file9_1.txt"
+        test_rename_no_extension, "file", file_autonamer ("file", DEFAULT_AUTONAME_TEMPLATE) => "file_1"
+        test_rename_template, "report_2024.txt", file_autonamer ("report_2024.txt", "{stem} ({n}){ext}") => "report_2024 (1).txt"
+        test_rename_template_padded, "photo-007.jpg", file_autonamer ("photo-007.jpg", "{stem}-{n:03}{ext}") => "photo-008.jpg"
+        test_rename_template_padded_start, "photo.jpg", file_autonamer ("photo.jpg", "{stem}-{n:03}{ext}") => "photo-001.jpg"
+        test_rename_multi_extension, "archive.tar.gz", file_autonamer ("archive.tar.gz", DEFAULT_AUTONAME_TEMPLATE) => "archive_1.tar.gz"
     );
 }
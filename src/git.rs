@@ -0,0 +1,422 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::filelist::Fingerprint;
+use crate::filesystem::{absolute_path, parent_dir, EntryKind, FileSystem, RealFileSystem};
+
+/// Filesystem of a git work tree, tracked paths are renamed with `git mv` so
+/// that the renames are staged, the others as usual.
+#[derive(Debug)]
+pub struct GitFileSystem {
+    root: PathBuf,
+    /// Tracked files, absolute, kept up to date as they are renamed.
+    tracked: Mutex<HashSet<PathBuf>>,
+    /// Tracked files differing from the index when the tree was opened.
+    unstaged: HashSet<PathBuf>,
+}
+
+/// Run git in `dir`, returning its stdout.
+fn git<I, S>(dir: &Path, args: I) -> io::Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Path printed by git, kept byte for byte so that non UTF-8 names match
+#[cfg(unix)]
+fn bytes_path(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).as_ref())
+}
+
+/// Paths of a `-z` listing, relative to `root`
+fn nul_separated(root: &Path, output: &[u8]) -> HashSet<PathBuf> {
+    output
+        .split(|byte| *byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| root.join(bytes_path(path)))
+        .collect()
+}
+
+/// Whether `path` or something below it is in `paths`
+fn contains_below(paths: &HashSet<PathBuf>, path: &Path) -> bool {
+    paths.contains(path) || (path.is_dir() && paths.iter().any(|other| other.starts_with(path)))
+}
+
+impl GitFileSystem {
+    /// Work tree holding `path`, a file or a dir, `None` when there is none
+    /// or git is missing.
+    pub fn open(path: &Path) -> io::Result<Option<Self>> {
+        let dir = if path.is_dir() {
+            path
+        } else {
+            parent_dir(path)
+        };
+        let root = match git(dir, ["rev-parse", "--show-toplevel"]) {
+            Ok(root) => bytes_path(root.strip_suffix(b"\n").unwrap_or(&root)),
+            Err(_) => return Ok(None),
+        };
+        let root = root.canonicalize()?;
+
+        let tracked = nul_separated(&root, &git(&root, ["ls-files", "-z"])?);
+        let unstaged = nul_separated(&root, &git(&root, ["diff", "--name-only", "-z"])?);
+
+        Ok(Some(Self {
+            root,
+            tracked: Mutex::new(tracked),
+            unstaged,
+        }))
+    }
+
+    /// Absolute path as git lists it, resolving the existing part of it.
+    fn resolve(&self, path: &Path) -> PathBuf {
+        let absolute = absolute_path(path);
+        match (absolute.parent(), absolute.file_name()) {
+            (Some(parent), Some(name)) => match fs::canonicalize(parent) {
+                Ok(parent) => parent.join(name),
+                Err(_) => absolute,
+            },
+            _ => absolute,
+        }
+    }
+
+    fn is_tracked(&self, path: &Path) -> bool {
+        contains_below(&self.tracked.lock().unwrap(), path)
+    }
+
+    /// Stage the new content of a tracked file.
+    fn stage(&self, path: &Path) -> io::Result<()> {
+        let path = self.resolve(path);
+        if !self.is_tracked(&path) {
+            return Ok(());
+        }
+
+        git(
+            &self.root,
+            [OsStr::new("add"), OsStr::new("--"), path.as_os_str()],
+        )?;
+
+        Ok(())
+    }
+}
+
+impl FileSystem for GitFileSystem {
+    fn exists(&self, path: &Path) -> bool {
+        RealFileSystem.exists(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        RealFileSystem.is_dir(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Fingerprint> {
+        RealFileSystem.metadata(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (self.resolve(from), self.resolve(to));
+        if !self.is_tracked(&from) {
+            return RealFileSystem.rename(&from, &to);
+        }
+
+        // conflicts have been dealt with by the plan, as for a plain rename
+        git(
+            &self.root,
+            [
+                OsStr::new("mv"),
+                OsStr::new("-f"),
+                OsStr::new("--"),
+                from.as_os_str(),
+                to.as_os_str(),
+            ],
+        )?;
+
+        let mut tracked = self.tracked.lock().unwrap();
+        let moved: Vec<PathBuf> = tracked
+            .iter()
+            .filter(|path| path.starts_with(&from))
+            .cloned()
+            .collect();
+        for path in moved {
+            tracked.remove(&path);
+            tracked.insert(to.join(path.strip_prefix(&from).unwrap()));
+        }
+
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        RealFileSystem.create_dir(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        RealFileSystem.remove_dir(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        RealFileSystem.copy(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        RealFileSystem.remove_file(path)
+    }
+
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = self.resolve(path);
+        let tracked = self.is_tracked(&path);
+        let trashed = RealFileSystem.trash(&path)?;
+
+        // stage the removal, the file itself is in the trash
+        if tracked {
+            git(
+                &self.root,
+                [
+                    OsStr::new("rm"),
+                    OsStr::new("-r"),
+                    OsStr::new("-q"),
+                    OsStr::new("--cached"),
+                    OsStr::new("--"),
+                    path.as_os_str(),
+                ],
+            )?;
+            self.tracked
+                .lock()
+                .unwrap()
+                .retain(|tracked| !tracked.starts_with(&path));
+        }
+
+        Ok(trashed)
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        RealFileSystem.set_mode(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+        RealFileSystem.set_owner(path, uid, gid)
+    }
+
+    fn set_mtime(&self, path: &Path, mtime: SystemTime) -> io::Result<()> {
+        RealFileSystem.set_mtime(path, mtime)
+    }
+
+    fn is_case_insensitive(&self, dir: &Path) -> bool {
+        RealFileSystem.is_case_insensitive(dir)
+    }
+
+    fn has_unstaged_changes(&self, path: &Path) -> bool {
+        contains_below(&self.unstaged, &self.resolve(path))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        RealFileSystem.read_link(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        RealFileSystem.symlink(target, link)?;
+        self.stage(link)
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        RealFileSystem.read_to_string(path)
+    }
+
+    fn rewrite(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        RealFileSystem.rewrite(path, content)?;
+        self.stage(path)
+    }
+
+    fn walk(&self, root: &Path) -> Vec<(PathBuf, EntryKind)> {
+        RealFileSystem.walk(root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{git, GitFileSystem};
+    use crate::config::Config;
+    use crate::errors::Error;
+    use crate::exec::{batch_operations, perfom_operations};
+    use crate::filelist::FileList;
+    use crate::filesystem::FileSystem;
+
+    /// Work tree with `a.txt` and `dir/b.txt` committed and `c.txt` untracked.
+    fn work_tree() -> tempfile::TempDir {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let root = tempdir.path();
+
+        fs::create_dir(root.join("dir")).unwrap();
+        for name in ["a.txt", "dir/b.txt", "c.txt"] {
+            fs::write(root.join(name), name).expect("Error creating mock file...");
+        }
+
+        git(root, ["init", "-q"]).expect("Error running git init");
+        git(root, ["add", "a.txt", "dir"]).unwrap();
+        git(
+            root,
+            [
+                "-c",
+                "user.name=rnr-buf",
+                "-c",
+                "user.email=rnr-buf@localhost",
+                "commit",
+                "-q",
+                "-m",
+                "init",
+            ],
+        )
+        .unwrap();
+
+        tempdir
+    }
+
+    fn status(root: &Path) -> String {
+        String::from_utf8(git(root, ["status", "--porcelain"]).unwrap()).unwrap()
+    }
+
+    fn rename(root: &Path, before: &[&str], after: &[&str], config: &Config) -> Result<(), Error> {
+        let fs = GitFileSystem::open(root).unwrap().expect("Not a work tree");
+        let lines = |names: &[&str]| -> String {
+            names
+                .iter()
+                .map(|name| format!("{}\n", root.join(name).display()))
+                .collect()
+        };
+
+        let original = FileList::new_from_raw(lines(before))?;
+        let modified = FileList::new_from_raw(lines(after))?;
+        let outcome = batch_operations(&fs, &original, &modified, config)?;
+        perfom_operations(&fs, &outcome, config)
+    }
+
+    fn config() -> Config {
+        Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        }
+    }
+
+    #[test]
+    fn test_open_outside_work_tree() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        assert!(GitFileSystem::open(tempdir.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_open_from_a_listed_file() {
+        let tempdir = work_tree();
+        let root = tempdir.path().canonicalize().unwrap();
+
+        let fs = GitFileSystem::open(&root.join("dir/b.txt"))
+            .unwrap()
+            .expect("Not a work tree");
+        assert_eq!(root, fs.root);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names_are_tracked() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let tempdir = work_tree();
+        let root = tempdir.path();
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        if fs::write(root.join(name), "x").is_err() {
+            // the filesystem only takes UTF-8 names
+            return;
+        }
+        git(root, [OsStr::new("add"), OsStr::new("--"), name]).unwrap();
+
+        let fs = GitFileSystem::open(root).unwrap().unwrap();
+        assert!(fs.is_tracked(&root.canonicalize().unwrap().join(name)));
+    }
+
+    #[test]
+    fn test_tracked_renames_are_staged() {
+        let tempdir = work_tree();
+        let root = tempdir.path();
+
+        rename(
+            root,
+            &["a.txt", "c.txt", "dir"],
+            &["d.txt", "e.txt", "other"],
+            &config(),
+        )
+        .unwrap();
+
+        let status = status(root);
+        assert!(status.contains("R  a.txt -> d.txt"), "{}", status);
+        assert!(status.contains("R  dir/b.txt -> other/b.txt"), "{}", status);
+        assert!(status.contains("?? e.txt"), "{}", status);
+        assert!(!root.join("c.txt").exists());
+    }
+
+    #[test]
+    fn test_swap_through_git() {
+        let tempdir = work_tree();
+        let root = tempdir.path();
+
+        rename(
+            root,
+            &["a.txt", "dir/b.txt"],
+            &["dir/b.txt", "a.txt"],
+            &config(),
+        )
+        .unwrap();
+
+        assert_eq!("a.txt", fs::read_to_string(root.join("dir/b.txt")).unwrap());
+        let status = status(root);
+        assert!(status.contains("M  a.txt"), "{}", status);
+        assert!(status.contains("M  dir/b.txt"), "{}", status);
+    }
+
+    #[test]
+    fn test_unstaged_changes_need_force() {
+        let tempdir = work_tree();
+        let root = tempdir.path();
+        fs::write(root.join("a.txt"), "changed").unwrap();
+
+        let fs = GitFileSystem::open(root).unwrap().unwrap();
+        assert!(fs.has_unstaged_changes(&root.join("a.txt")));
+        assert!(!fs.has_unstaged_changes(&root.join("dir")));
+
+        assert!(matches!(
+            rename(root, &["a.txt"], &["d.txt"], &config()),
+            Err(Error::UnstagedChanges { line: 1, .. })
+        ));
+        assert!(root.join("a.txt").exists());
+
+        let config = Config {
+            force: true,
+            ..config()
+        };
+        rename(root, &["a.txt"], &["d.txt"], &config).unwrap();
+        assert_eq!("changed", fs::read_to_string(root.join("d.txt")).unwrap());
+    }
+}
@@ -0,0 +1,162 @@
+//! Rename files by editing a buffer of paths.
+//!
+//! A [`FileList`] holds the paths as listed and as edited, [`plan`] pairs
+//! them into typed [`Activity`] renames and [`execute()`] runs them.
+//!
+//! ```
+//! use rnr_buf::{Config, FileList};
+//!
+//! let dir = tempfile::tempdir()?;
+//! std::fs::write(dir.path().join("a.txt"), "a")?;
+//!
+//! let original = rnr_buf::parse_buffer(&format!("{}/a.txt\n", dir.path().display()))?;
+//! let modified = rnr_buf::parse_buffer(&format!("{}/b.txt\n", dir.path().display()))?;
+//!
+//! let config = Config {
+//!     yes: true,
+//!     quiet: true,
+//!     ..Config::new()
+//! };
+//!
+//! let activities = rnr_buf::plan(&original, &modified, &config)?;
+//! assert_eq!(dir.path().join("b.txt"), activities[0].destination);
+//!
+//! rnr_buf::execute(&activities, &config)?;
+//! assert!(dir.path().join("b.txt").exists());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`plan_with`] and [`execute_with`] take any [`FileSystem`], such as the
+//! in-memory [`MemoryFileSystem`]:
+//!
+//! ```
+//! use std::path::Path;
+//!
+//! use rnr_buf::{Config, MemoryFileSystem};
+//!
+//! let fs = MemoryFileSystem::with_files(["a.txt"]);
+//! let original = rnr_buf::parse_buffer("a.txt\n")?;
+//! let modified = rnr_buf::parse_buffer("docs/a.txt\n")?;
+//! let config = Config {
+//!     mkdir: true,
+//!     yes: true,
+//!     quiet: true,
+//!     ..Config::new()
+//! };
+//!
+//! let activities = rnr_buf::plan_with(&fs, &original, &modified, &config)?;
+//! rnr_buf::execute_with(&fs, &activities, &config)?;
+//! assert!(fs.read(Path::new("docs/a.txt")).is_some());
+//! # Ok::<(), rnr_buf::Error>(())
+//! ```
+
+mod columns;
+mod config;
+mod errors;
+mod exec;
+mod filelist;
+mod filesystem;
+mod git;
+mod media;
+mod memory;
+mod naming;
+mod plan;
+mod planfile;
+mod recovery;
+mod references;
+mod symlinks;
+mod template;
+mod transform;
+mod trash;
+
+pub use crate::columns::{AttributeChange, Attributes, Column, Owner};
+pub use crate::config::{Config, ConfigValues, ConflictStrategy};
+pub use crate::errors::{error_string, Error};
+pub use crate::exec::{list_files, open_editor, Activity};
+pub use crate::filelist::{FileDirPosition, FileList, Fingerprint};
+pub use crate::filesystem::{EntryKind, FileSystem, RealFileSystem};
+pub use crate::git::GitFileSystem;
+pub use crate::memory::MemoryFileSystem;
+pub use crate::naming::rename_buffer;
+pub use crate::planfile::{Operation, PlanFile};
+pub use crate::recovery::{load_buffer, save_buffer, settle, state_dir};
+pub use crate::references::{ReferenceKind, ReferenceUpdate, Replacement};
+pub use crate::symlinks::SymlinkFix;
+pub use crate::transform::Transform;
+
+/// Read a buffer of paths, one per line, as written by the user.
+///
+/// ```
+/// let list = rnr_buf::parse_buffer("# comment\nsrc/a.txt\nsrc/b.txt\n")?;
+/// assert_eq!(2, list.list.len());
+///
+/// let err = rnr_buf::parse_buffer("a.txt\nb.txt\na.txt\n").unwrap_err();
+/// assert!(matches!(
+///     err,
+///     rnr_buf::Error::DuplicatePath { line: 3, first_line: 1, .. }
+/// ));
+/// # Ok::<(), rnr_buf::Error>(())
+/// ```
+pub fn parse_buffer(raw: &str) -> Result<FileList, Error> {
+    FileList::new_from_raw(raw.to_owned())
+}
+
+/// Same as [`parse_buffer`], for the buffer format selected by `config`:
+/// extra columns before each path, two-column lines or both. The left column
+/// of an edited two-column buffer has to be a path of `original`, which is
+/// `None` when reading a listing itself.
+pub fn parse_buffer_with(
+    raw: &str,
+    original: Option<&FileList>,
+    config: &Config,
+) -> Result<FileList, Error> {
+    if config.two_column {
+        FileList::new_two_column(raw.to_owned(), &config.columns, original)
+    } else {
+        FileList::new_with_columns(raw.to_owned(), &config.columns)
+    }
+}
+
+/// Pair the original and the edited lists into the renames to run, in order.
+pub fn plan(
+    original: &FileList,
+    modified: &FileList,
+    config: &Config,
+) -> Result<Vec<Activity>, Error> {
+    plan_with(&RealFileSystem, original, modified, config)
+}
+
+/// Same as [`plan`], looking the paths up in `fs`.
+pub fn plan_with(
+    fs: &dyn FileSystem,
+    original: &FileList,
+    modified: &FileList,
+    config: &Config,
+) -> Result<Vec<Activity>, Error> {
+    exec::batch_operations(fs, original, modified, config)
+}
+
+/// Run the renames of a plan.
+pub fn execute(activities: &[Activity], config: &Config) -> Result<(), Error> {
+    execute_with(&RealFileSystem, activities, config)
+}
+
+/// Same as [`execute()`], renaming in `fs`.
+pub fn execute_with(
+    fs: &dyn FileSystem,
+    activities: &[Activity],
+    config: &Config,
+) -> Result<(), Error> {
+    exec::perfom_operations(fs, activities, config)
+}
+
+/// Same as [`execute_with`], pushing the index of each activity to
+/// `completed` once it has run, to [`settle`] a run that failed halfway.
+pub fn execute_tracked(
+    fs: &dyn FileSystem,
+    activities: &[Activity],
+    config: &Config,
+    completed: &mut Vec<usize>,
+) -> Result<(), Error> {
+    exec::perfom_operations_tracked(fs, activities, config, completed)
+}
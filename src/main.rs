@@ -1,27 +1,26 @@
 mod cli;
-mod config;
-mod errors;
-mod exec;
-mod filelist;
-mod filesystem;
 
+use std::env;
+use std::path::Path;
 use std::process::exit;
 
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 use clap::Parser;
 
-use crate::cli::Opts;
-use crate::config::Config;
-use crate::errors::error_string;
-use crate::exec::{batch_operations, list_files, open_editor, perfom_operations};
-use crate::filelist::FileList;
+use crate::cli::{Command, Opts};
+use rnr_buf::{
+    error_string, list_files, load_buffer, open_editor, rename_buffer, save_buffer, settle,
+    state_dir, Config, FileList, FileSystem, GitFileSystem, PlanFile, RealFileSystem,
+};
 
 fn main() {
     let result = run();
 
     match result {
         Err(err) => {
-            println!("{}{:#}", error_string(), err);
+            for line in format!("{:#}", err).lines() {
+                println!("{}{}", error_string(), line);
+            }
             exit(1);
         }
         _ => {
@@ -33,21 +32,127 @@ fn main() {
 fn run() -> Result<(), anyhow::Error> {
     let opts = Opts::parse();
 
-    if opts.paths.is_empty() {
-        bail!("No valid paths given.");
+    let config = Config::load(
+        &opts.config_values(),
+        opts.profile.as_deref(),
+        opts.no_config,
+    )?;
+
+    let (paths, out) = match opts.command {
+        Some(Command::Apply { plan }) => {
+            // the strategies were picked when planning, one per operation
+            if opts.on_conflict.is_some() || opts.automatic_rename {
+                bail!("`--on-conflict` cannot be given to `apply`, the plan records it");
+            }
+            return apply_plan(&plan, &config);
+        }
+        Some(Command::Plan { out, paths }) => (paths, Some(out)),
+        None => (opts.paths, None),
+    };
+
+    let (original, buffer) = match &opts.resume {
+        Some(path) => load_buffer(path, &config)?,
+        None => {
+            if paths.is_empty() {
+                bail!("No valid paths given.");
+            }
+
+            let original = list_files(paths, &config)?;
+            let buffer = rename_buffer(&original, &config)?;
+            (original, buffer)
+        }
+    };
+
+    // the work tree is the one of the listed paths, not of the current dir
+    let target = original
+        .list
+        .first()
+        .map(|file| file.source.clone())
+        .unwrap_or_default();
+    let fs = file_system(&target, &config)?;
+
+    let raw = if config.no_editor {
+        buffer
+    } else {
+        open_editor(&buffer, &config)?
+    };
+
+    let mut settled = None;
+    if let Err(err) = apply(
+        fs.as_ref(),
+        &original,
+        &raw,
+        &config,
+        out.as_deref(),
+        &mut settled,
+    ) {
+        // renames that ran are not to be resumed
+        let (original, raw) = settled.unwrap_or((original, raw));
+        let path = save_buffer(&state_dir(), &original, &raw, &err)?;
+        return Err(err.context(format!(
+            "Edited buffer saved, continue with `--resume {}`",
+            path.display()
+        )));
     }
 
-    let config = Config::from_args(&opts);
+    Ok(())
+}
 
-    let original = list_files(opts.paths, &config)?;
+/// Rename through git when `target` is inside a work tree, unless told
+/// otherwise.
+fn file_system(target: &Path, config: &Config) -> Result<Box<dyn FileSystem>, anyhow::Error> {
+    if config.git == Some(false) {
+        return Ok(Box::new(RealFileSystem));
+    }
 
-    let raw = open_editor(&original.raw, &config)?;
+    match GitFileSystem::open(target)? {
+        Some(git) => Ok(Box::new(git)),
+        None if config.git == Some(true) => Err(anyhow!("Not inside a git work tree.")),
+        None => Ok(Box::new(RealFileSystem)),
+    }
+}
+
+/// Plan the edited buffer and run it, or write the plan to `out`. A run that
+/// fails halfway leaves in `settled` the listing and buffer to resume from.
+fn apply(
+    fs: &dyn FileSystem,
+    original: &FileList,
+    raw: &str,
+    config: &Config,
+    out: Option<&Path>,
+    settled: &mut Option<(FileList, String)>,
+) -> Result<(), anyhow::Error> {
+    let modified = rnr_buf::parse_buffer_with(raw, Some(original), config)?;
+
+    let outcome = rnr_buf::plan_with(fs, original, &modified, config)?;
+
+    match out {
+        Some(out) => {
+            PlanFile::from_activities(&outcome, &env::current_dir()?).save(out)?;
+            if !config.quiet {
+                println!("Plan written to {:?}", out);
+            }
+        }
+        None => {
+            let mut completed = Vec::new();
+            let result = rnr_buf::execute_tracked(fs, &outcome, config, &mut completed);
+            if result.is_err() && !completed.is_empty() {
+                *settled = Some(settle(original, raw, &outcome, &completed));
+            }
+            result?
+        }
+    }
 
-    let modified = FileList::new_from_raw(raw)?;
+    Ok(())
+}
 
-    let outcome = batch_operations(&original, &modified, &config)?;
+/// Run a saved plan, once checked against the current tree.
+fn apply_plan(path: &Path, config: &Config) -> Result<(), anyhow::Error> {
+    let plan = PlanFile::load(path)?;
+    let fs = file_system(&plan.root, config)?;
 
-    perfom_operations(&outcome, &config)?;
+    let outcome = plan.activities(fs.as_ref(), config)?;
+    rnr_buf::execute_with(fs.as_ref(), &outcome, config)?;
 
     Ok(())
 }
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use exif::{In, Reader, Value};
+use id3::TagLike;
+
+/// How EXIF writes dates, e.g. `2024:01:31 12:00:00`.
+const EXIF_DATE_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// Tags known to the `{id3:<tag>}` placeholders.
+pub const AUDIO_TAGS: &[&str] = &[
+    "artist",
+    "albumartist",
+    "album",
+    "title",
+    "track",
+    "disc",
+    "year",
+    "genre",
+];
+
+/// Comment headers larger than this are not read, they hold cover art at most.
+const MAX_COMMENT_SIZE: usize = 16 << 20;
+
+/// EXIF fields of an image by tag name, e.g. `Model` or `DateTimeOriginal`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExifTags(HashMap<String, String>);
+
+impl ExifTags {
+    /// Read the EXIF data of a JPEG, TIFF, HEIF, PNG or WebP file, `None` when
+    /// it has none.
+    pub fn of(path: &Path) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let exif = Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .ok()?;
+
+        // the primary image wins over the thumbnail
+        let mut tags = HashMap::new();
+        for field in exif.fields() {
+            let value = match &field.value {
+                Value::Ascii(strings) => strings
+                    .iter()
+                    .map(|string| String::from_utf8_lossy(string).trim().to_owned())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                _ => field.display_value().to_string(),
+            };
+
+            let name = field.tag.to_string();
+            if field.ifd_num == In::PRIMARY || !tags.contains_key(&name) {
+                tags.insert(name, value);
+            }
+        }
+
+        Some(Self(tags))
+    }
+
+    pub fn get(&self, tag: &str) -> Option<&str> {
+        self.0
+            .get(tag)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Date of a `DateTime*` tag, local to where the picture was taken.
+    pub fn date(&self, tag: &str) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(self.get(tag)?, EXIF_DATE_FORMAT).ok()
+    }
+}
+
+/// Whether `{exif:<tag>}` is a date, formatted and replaced by the mtime
+/// when missing.
+pub fn is_date_tag(tag: &str) -> bool {
+    tag.starts_with("DateTime")
+}
+
+/// Tags of a music file by the names of [`AUDIO_TAGS`], numbers without
+/// their total (`3` for a `3/12` track).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioTags(HashMap<String, String>);
+
+impl AudioTags {
+    /// Read the ID3 tags of an MP3 file or the Vorbis comments of a FLAC, Ogg
+    /// Vorbis or Opus file, `None` when it has none.
+    pub fn of(path: &Path) -> Option<Self> {
+        let mut file = BufReader::new(File::open(path).ok()?);
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic).ok()?;
+
+        let tags = match &magic {
+            b"fLaC" => vorbis_comments(&flac_comments(&mut file).ok()??)?,
+            b"OggS" => {
+                file.seek(SeekFrom::Start(0)).ok()?;
+                vorbis_comments(&ogg_comments(&mut file).ok()??)?
+            }
+            _ => id3_tags(&id3::v1v2::read_from_path(path).ok()?),
+        };
+
+        Some(Self(tags))
+    }
+
+    pub fn get(&self, tag: &str) -> Option<&str> {
+        self.0.get(tag).map(String::as_str)
+    }
+}
+
+fn id3_tags(tag: &id3::Tag) -> HashMap<String, String> {
+    let tags = [
+        ("artist", tag.artist().map(str::to_owned)),
+        ("albumartist", tag.album_artist().map(str::to_owned)),
+        ("album", tag.album().map(str::to_owned)),
+        ("title", tag.title().map(str::to_owned)),
+        ("track", tag.track().map(|track| track.to_string())),
+        ("disc", tag.disc().map(|disc| disc.to_string())),
+        ("year", tag.year().map(|year| year.to_string())),
+        ("genre", tag.genre_parsed().map(|genre| genre.into_owned())),
+    ];
+
+    tags.into_iter()
+        .filter_map(|(name, value)| Some((name.to_owned(), value?)))
+        .filter(|(_, value)| !value.trim().is_empty())
+        .collect()
+}
+
+/// Parse a Vorbis comment header, without its packet type: the vendor
+/// string then `KEY=value` comments, lengths being little endian.
+fn vorbis_comments(data: &[u8]) -> Option<HashMap<String, String>> {
+    let mut rest = data;
+    read_string(&mut rest)?;
+    let count = read_u32(&mut rest)?;
+    let mut tags = HashMap::new();
+    for _ in 0..count {
+        let comment = String::from_utf8_lossy(read_string(&mut rest)?).into_owned();
+        let Some((key, value)) = comment.split_once('=') else {
+            continue;
+        };
+
+        let value = value.trim();
+        let (name, value) = match key.to_uppercase().as_str() {
+            "ARTIST" => ("artist", value),
+            "ALBUMARTIST" | "ALBUM ARTIST" => ("albumartist", value),
+            "ALBUM" => ("album", value),
+            "TITLE" => ("title", value),
+            "TRACKNUMBER" => ("track", value.split('/').next().unwrap_or(value)),
+            "DISCNUMBER" => ("disc", value.split('/').next().unwrap_or(value)),
+            "DATE" | "YEAR" => ("year", value.get(..4).unwrap_or(value)),
+            "GENRE" => ("genre", value),
+            _ => continue,
+        };
+        if !value.is_empty() {
+            tags.entry(name.to_owned())
+                .or_insert_with(|| value.to_owned());
+        }
+    }
+
+    Some(tags)
+}
+
+fn read_u32(rest: &mut &[u8]) -> Option<u32> {
+    let bytes = rest.get(..4)?.try_into().ok()?;
+    *rest = &rest[4..];
+    Some(u32::from_le_bytes(bytes))
+}
+
+/// A length prefixed string of a Vorbis comment header.
+fn read_string<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let length = read_u32(rest)? as usize;
+    let value = rest.get(..length)?;
+    *rest = &rest[length..];
+    Some(value)
+}
+
+/// The `VORBIS_COMMENT` block of a FLAC file, read after its `fLaC` marker.
+fn flac_comments(file: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut header = [0; 4];
+        file.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+        let mut block = vec![0; length];
+        file.read_exact(&mut block)?;
+        if header[0] & 0x7f == 4 {
+            return Ok(Some(block));
+        }
+        if header[0] & 0x80 != 0 {
+            return Ok(None);
+        }
+    }
+}
+
+/// The comment header of an Ogg Vorbis or Opus file, its second packet.
+fn ogg_comments(file: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut packets = 0;
+    let mut packet = Vec::new();
+    while packet.len() < MAX_COMMENT_SIZE {
+        // capture pattern, version, header type, granule, serial, sequence
+        // number, checksum and the number of segments
+        let mut header = [0; 27];
+        file.read_exact(&mut header)?;
+        if &header[..4] != b"OggS" {
+            return Ok(None);
+        }
+
+        let mut segments = vec![0; header[26] as usize];
+        file.read_exact(&mut segments)?;
+        for length in segments {
+            let start = packet.len();
+            packet.resize(start + length as usize, 0);
+            file.read_exact(&mut packet[start..])?;
+            if length == 255 {
+                continue;
+            }
+
+            // a segment shorter than 255 bytes ends the packet
+            packets += 1;
+            if packets == 2 {
+                let comments = packet
+                    .strip_prefix(b"\x03vorbis")
+                    .or_else(|| packet.strip_prefix(b"OpusTags"));
+                return Ok(comments.map(<[u8]>::to_vec));
+            }
+            packet.clear();
+        }
+    }
+
+    Ok(None)
+}
+
+/// Width and height of an image in pixels, reading only its header.
+pub fn image_size(path: &Path) -> Option<(usize, usize)> {
+    let size = imagesize::size(path).ok()?;
+
+    Some((size.width, size.height))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+
+    use exif::experimental::Writer;
+    use exif::{Field, In, Tag, Value};
+
+    use super::{image_size, AudioTags, ExifTags};
+
+    #[test]
+    fn test_exif_and_size() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let path = tempdir.path().join("photo.tif");
+
+        let fields = [
+            (Tag::Model, Value::Ascii(vec![b"Pixel 8".to_vec()])),
+            (
+                Tag::DateTimeOriginal,
+                Value::Ascii(vec![b"2024:01:31 12:30:00".to_vec()]),
+            ),
+            (Tag::ImageWidth, Value::Long(vec![640])),
+            (Tag::ImageLength, Value::Long(vec![480])),
+        ]
+        .map(|(tag, value)| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        });
+        let mut writer = Writer::new();
+        for field in fields.iter() {
+            writer.push_field(field);
+        }
+        writer
+            .write(&mut File::create(&path).unwrap(), true)
+            .unwrap();
+
+        let tags = ExifTags::of(&path).expect("Missing EXIF data");
+        assert_eq!(Some("Pixel 8"), tags.get("Model"));
+        assert_eq!(
+            "2024-01-31",
+            tags.date("DateTimeOriginal")
+                .unwrap()
+                .format("%Y-%m-%d")
+                .to_string()
+        );
+        assert_eq!(None, tags.get("Make"));
+        assert_eq!(Some((640, 480)), image_size(&path));
+        assert_eq!(None, ExifTags::of(tempdir.path()));
+    }
+
+    /// Vorbis comment header holding `comments`.
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let push_string = |data: &mut Vec<u8>, string: &str| {
+            data.extend((string.len() as u32).to_le_bytes());
+            data.extend(string.as_bytes());
+        };
+
+        let mut data = Vec::new();
+        push_string(&mut data, "rnr-buf");
+        data.extend((comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            push_string(&mut data, comment);
+        }
+
+        data
+    }
+
+    #[test]
+    fn test_audio_tags() {
+        use id3::TagLike;
+        use std::fs;
+
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+
+        let mp3 = tempdir.path().join("song.mp3");
+        fs::write(&mp3, b"").unwrap();
+        let mut tag = id3::Tag::new();
+        tag.set_artist("AC/DC");
+        tag.set_track(3);
+        tag.write_to_path(&mp3, id3::Version::Id3v24).unwrap();
+
+        let tags = AudioTags::of(&mp3).expect("Missing ID3 tags");
+        assert_eq!(Some("AC/DC"), tags.get("artist"));
+        assert_eq!(Some("3"), tags.get("track"));
+        assert_eq!(None, tags.get("album"));
+
+        let comments = vorbis_comment(&["TITLE=Intro", "tracknumber=2/12", "DATE=2001-05-01"]);
+        let mut flac = b"fLaC".to_vec();
+        flac.extend([0x00, 0, 0, 2, 0xff, 0xff]);
+        flac.push(0x84);
+        flac.extend(&(comments.len() as u32).to_be_bytes()[1..]);
+        flac.extend(&comments);
+        let path = tempdir.path().join("song.flac");
+        fs::write(&path, flac).unwrap();
+
+        let tags = AudioTags::of(&path).expect("Missing Vorbis comments");
+        assert_eq!(Some("Intro"), tags.get("title"));
+        assert_eq!(Some("2"), tags.get("track"));
+        assert_eq!(Some("2001"), tags.get("year"));
+
+        // one page holding the identification and the comment packets
+        let packets = [
+            b"\x01vorbis".to_vec(),
+            [b"\x03vorbis".to_vec(), comments].concat(),
+        ];
+        let mut ogg = b"OggS".to_vec();
+        ogg.extend([0; 22]);
+        ogg.push(packets.len() as u8);
+        ogg.extend(packets.iter().map(|packet| packet.len() as u8));
+        ogg.extend(packets.concat());
+        let path = tempdir.path().join("song.ogg");
+        fs::write(&path, ogg).unwrap();
+
+        let tags = AudioTags::of(&path).expect("Missing Vorbis comments");
+        assert_eq!(Some("Intro"), tags.get("title"));
+        assert_eq!(None, AudioTags::of(tempdir.path()));
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::filelist::Fingerprint;
+use crate::filesystem::{case_fold, EntryKind, FileSystem};
+
+/// Trash of the tree, entries are moved to its `files` dir.
+pub const TRASH: &str = ".Trash";
+
+#[derive(Debug, Clone)]
+enum Node {
+    Dir,
+    File(Vec<u8>),
+    Symlink(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    node: Node,
+    ino: u64,
+    mtime: SystemTime,
+    mode: u32,
+    owner: (u32, u32),
+}
+
+#[derive(Debug, Default)]
+struct Tree {
+    entries: BTreeMap<PathBuf, Entry>,
+    next_ino: u64,
+    case_insensitive: bool,
+}
+
+/// Filesystem kept in memory, to test planning and execution without
+/// touching the disk. It is case-sensitive unless made with
+/// [`MemoryFileSystem::case_insensitive`] and `.`/`/` always exist.
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    tree: Mutex<Tree>,
+}
+
+/// Drop `.` components so that `./a` and `a` are the same entry.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+fn is_root(path: &Path) -> bool {
+    path.as_os_str().is_empty() || path.parent().is_none()
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{:?} not found", path))
+}
+
+impl Tree {
+    /// Path of the entry `path` names, which may differ by case on a
+    /// case-insensitive tree. Missing components keep the given case.
+    fn resolve(&self, path: &Path) -> PathBuf {
+        let path = normalize(path);
+        if !self.case_insensitive {
+            return path;
+        }
+
+        let mut resolved = PathBuf::new();
+        for component in path.components() {
+            let wanted = case_fold(&resolved.join(component));
+            resolved = self
+                .entries
+                .keys()
+                .find(|key| key.parent() == Some(&resolved) && case_fold(key) == wanted)
+                .cloned()
+                .unwrap_or_else(|| resolved.join(component));
+        }
+
+        resolved
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        is_root(path)
+            || matches!(
+                self.entries.get(path),
+                Some(Entry {
+                    node: Node::Dir,
+                    ..
+                })
+            )
+    }
+
+    fn insert(&mut self, path: PathBuf, node: Node) {
+        self.next_ino += 1;
+        let entry = Entry {
+            ino: self.next_ino,
+            // every entry gets its own mtime, the way writes would
+            mtime: UNIX_EPOCH + Duration::from_secs(self.next_ino),
+            mode: match node {
+                Node::Dir => 0o755,
+                Node::File(_) => 0o644,
+                Node::Symlink(_) => 0o777,
+            },
+            owner: (0, 0),
+            node,
+        };
+        self.entries.insert(path, entry);
+    }
+
+    fn check_parent(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) if !self.is_dir(parent) => Err(not_found(parent)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Empty tree where names differing by case are the same entry, as on
+    /// macOS and Windows. Entries keep the case they were created with.
+    pub fn case_insensitive() -> Self {
+        let fs = Self::new();
+        fs.tree.lock().unwrap().case_insensitive = true;
+
+        fs
+    }
+
+    /// Tree made of the given files, with their parent dirs and a content
+    /// equal to their path.
+    pub fn with_files<I, P>(files: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let fs = Self::new();
+        for file in files {
+            let file = file.as_ref();
+            fs.write(file, file.to_string_lossy().as_bytes());
+        }
+
+        fs
+    }
+
+    /// Create or replace a file, along with its parent dirs.
+    pub fn write(&self, path: &Path, content: &[u8]) {
+        let mut tree = self.tree.lock().unwrap();
+        let path = tree.resolve(path);
+
+        let mut dirs: Vec<&Path> = path.ancestors().skip(1).collect();
+        dirs.reverse();
+        for dir in dirs {
+            if !tree.is_dir(dir) {
+                tree.insert(dir.to_path_buf(), Node::Dir);
+            }
+        }
+
+        tree.insert(path, Node::File(content.to_vec()));
+    }
+
+    /// Content of a file, `None` for dirs and missing paths.
+    pub fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        let tree = self.tree.lock().unwrap();
+        match tree.entries.get(&tree.resolve(path)) {
+            Some(Entry {
+                node: Node::File(content),
+                ..
+            }) => Some(content.clone()),
+            _ => None,
+        }
+    }
+
+    /// Permission bits and owner ids of a path.
+    pub fn attributes(&self, path: &Path) -> Option<(u32, u32, u32)> {
+        let tree = self.tree.lock().unwrap();
+        tree.entries
+            .get(&tree.resolve(path))
+            .map(|entry| (entry.mode, entry.owner.0, entry.owner.1))
+    }
+
+    /// Every file of the tree with its content, sorted by path.
+    pub fn files(&self) -> Vec<(PathBuf, Vec<u8>)> {
+        let tree = self.tree.lock().unwrap();
+        tree.entries
+            .iter()
+            .filter_map(|(path, entry)| match &entry.node {
+                Node::File(content) => Some((path.clone(), content.clone())),
+                Node::Dir | Node::Symlink(_) => None,
+            })
+            .collect()
+    }
+
+    fn update(&self, path: &Path, change: impl FnOnce(&mut Entry)) -> io::Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let path = tree.resolve(path);
+        let entry = tree
+            .entries
+            .get_mut(&path)
+            .ok_or_else(|| not_found(&path))?;
+        change(entry);
+
+        Ok(())
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn exists(&self, path: &Path) -> bool {
+        let tree = self.tree.lock().unwrap();
+        let path = tree.resolve(path);
+        is_root(&path) || tree.entries.contains_key(&path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        let tree = self.tree.lock().unwrap();
+        tree.is_dir(&tree.resolve(path))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Fingerprint> {
+        let tree = self.tree.lock().unwrap();
+        let path = tree.resolve(path);
+        let entry = tree.entries.get(&path).ok_or_else(|| not_found(&path))?;
+
+        Ok(Fingerprint {
+            dev: 0,
+            ino: entry.ino,
+            is_dir: matches!(entry.node, Node::Dir),
+            size: match &entry.node {
+                Node::File(content) => content.len() as u64,
+                Node::Symlink(target) => target.as_os_str().len() as u64,
+                Node::Dir => 0,
+            },
+            mtime: Some(entry.mtime),
+        })
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let (from, target) = (tree.resolve(from), tree.resolve(to));
+        // the new name keeps its case, even over an entry spelled differently
+        let to = match (target.parent(), normalize(to).file_name()) {
+            (Some(parent), Some(name)) => parent.join(name),
+            _ => target.clone(),
+        };
+
+        let entry = tree.entries.get(&from).ok_or_else(|| not_found(&from))?;
+        tree.check_parent(&to)?;
+        if from == to {
+            return Ok(());
+        }
+
+        let is_dir = matches!(entry.node, Node::Dir);
+        match tree.entries.get(&target).filter(|_| target != from) {
+            Some(Entry {
+                node: Node::Dir, ..
+            }) if !is_dir => {
+                return Err(io::Error::other(format!("{:?} is a directory", to)));
+            }
+            Some(Entry {
+                node: Node::File(_) | Node::Symlink(_),
+                ..
+            }) if is_dir => {
+                return Err(io::Error::other(format!("{:?} is not a directory", to)));
+            }
+            _ => {}
+        }
+        if to.starts_with(&from) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} cannot be moved inside itself", from),
+            ));
+        }
+
+        if target != from && target != to {
+            tree.entries.remove(&target);
+        }
+
+        // a directory takes all of its content along, paths sort by
+        // component so it follows the directory itself
+        let moved: Vec<PathBuf> = tree
+            .entries
+            .range(from.clone()..)
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(&from))
+            .cloned()
+            .collect();
+        for path in moved {
+            let entry = tree.entries.remove(&path).unwrap();
+            let suffix = path.strip_prefix(&from).unwrap();
+            let target = if suffix.as_os_str().is_empty() {
+                to.clone()
+            } else {
+                to.join(suffix)
+            };
+            tree.entries.insert(target, entry);
+        }
+
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let path = tree.resolve(path);
+
+        if is_root(&path) || tree.entries.contains_key(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} exists", path),
+            ));
+        }
+        tree.check_parent(&path)?;
+        tree.insert(path, Node::Dir);
+
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let mut tree = self.tree.lock().unwrap();
+        let (from, to) = (tree.resolve(from), tree.resolve(to));
+
+        let content = match tree.entries.get(&from) {
+            Some(Entry {
+                node: Node::File(content),
+                ..
+            }) => content.clone(),
+            Some(_) => return Err(io::Error::other(format!("{:?} is a directory", from))),
+            None => return Err(not_found(&from)),
+        };
+        tree.check_parent(&to)?;
+
+        let size = content.len() as u64;
+        tree.insert(to, Node::File(content));
+
+        Ok(size)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let path = tree.resolve(path);
+
+        match tree.entries.get(&path) {
+            Some(Entry {
+                node: Node::File(_) | Node::Symlink(_),
+                ..
+            }) => {
+                tree.entries.remove(&path);
+                Ok(())
+            }
+            Some(_) => Err(io::Error::other(format!("{:?} is a directory", path))),
+            None => Err(not_found(&path)),
+        }
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.update(path, |entry| entry.mode = mode)
+    }
+
+    fn set_owner(&self, path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+        self.update(path, |entry| entry.owner = (uid, gid))
+    }
+
+    fn set_mtime(&self, path: &Path, mtime: SystemTime) -> io::Result<()> {
+        self.update(path, |entry| entry.mtime = mtime)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let path = tree.resolve(path);
+
+        match tree.entries.get(&path) {
+            Some(Entry {
+                node: Node::Dir, ..
+            }) => {
+                if tree
+                    .entries
+                    .range(path.clone()..)
+                    .nth(1)
+                    .is_some_and(|(other, _)| other.starts_with(&path))
+                {
+                    return Err(io::Error::other(format!("{:?} is not empty", path)));
+                }
+                tree.entries.remove(&path);
+                Ok(())
+            }
+            Some(_) => Err(io::Error::other(format!("{:?} is not a directory", path))),
+            None => Err(not_found(&path)),
+        }
+    }
+
+    fn trash(&self, path: &Path) -> io::Result<PathBuf> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| not_found(path))?
+            .to_string_lossy()
+            .into_owned();
+
+        let files = Path::new(TRASH).join("files");
+        for dir in [Path::new(TRASH), &files] {
+            if !self.is_dir(dir) {
+                self.create_dir(dir)?;
+            }
+        }
+
+        let mut trashed = files.join(&name);
+        let mut number = 1;
+        while self.exists(&trashed) {
+            number += 1;
+            trashed = files.join(format!("{}.{}", name, number));
+        }
+        self.rename(path, &trashed)?;
+
+        Ok(trashed)
+    }
+
+    fn is_case_insensitive(&self, _dir: &Path) -> bool {
+        self.tree.lock().unwrap().case_insensitive
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        let tree = self.tree.lock().unwrap();
+        let path = tree.resolve(path);
+        match tree.entries.get(&path) {
+            Some(Entry {
+                node: Node::Symlink(target),
+                ..
+            }) => Ok(target.clone()),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not a symlink", path),
+            )),
+            None => Err(not_found(&path)),
+        }
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let link = tree.resolve(link);
+
+        if is_root(&link) || tree.entries.contains_key(&link) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} exists", link),
+            ));
+        }
+        tree.check_parent(&link)?;
+        tree.insert(link, Node::Symlink(target.to_path_buf()));
+
+        Ok(())
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let content = self.read(path).ok_or_else(|| not_found(path))?;
+
+        String::from_utf8(content).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn rewrite(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        let mut tree = self.tree.lock().unwrap();
+        let path = tree.resolve(path);
+
+        match tree.entries.get_mut(&path) {
+            Some(Entry {
+                node: Node::File(old),
+                ..
+            }) => {
+                *old = content.to_vec();
+                Ok(())
+            }
+            Some(_) => Err(io::Error::other(format!("{:?} is not a file", path))),
+            None => Err(not_found(&path)),
+        }
+    }
+
+    fn walk(&self, root: &Path) -> Vec<(PathBuf, EntryKind)> {
+        let tree = self.tree.lock().unwrap();
+        let root = tree.resolve(root);
+        tree.entries
+            .iter()
+            .filter(|(path, _)| path.starts_with(&root) || is_root(&root))
+            .filter(|(path, _)| !path.iter().any(|component| component == ".git"))
+            .map(|(path, entry)| {
+                let kind = match entry.node {
+                    Node::Dir => EntryKind::Dir,
+                    Node::File(_) => EntryKind::File,
+                    Node::Symlink(_) => EntryKind::Symlink,
+                };
+
+                (path.clone(), kind)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use super::MemoryFileSystem;
+    use crate::filesystem::FileSystem;
+
+    #[test]
+    fn test_case_insensitive() {
+        let fs = MemoryFileSystem::case_insensitive();
+        fs.write(Path::new("Dir/A.txt"), b"a");
+
+        assert!(fs.exists(Path::new("dir/a.TXT")));
+        fs.rename(Path::new("dir/a.txt"), Path::new("DIR/a.txt"))
+            .unwrap();
+        assert_eq!(
+            vec![(PathBuf::from("Dir/a.txt"), b"a".to_vec())],
+            fs.files()
+        );
+    }
+
+    #[test]
+    fn test_rename_dir() {
+        let fs = MemoryFileSystem::with_files(["a/b/c.txt", "a/d.txt"]);
+        fs.rename(Path::new("a/b"), Path::new("./e")).unwrap();
+
+        assert!(fs.is_dir(Path::new("e")));
+        assert_eq!(Some(b"a/b/c.txt".to_vec()), fs.read(Path::new("e/c.txt")));
+        assert!(!fs.exists(Path::new("a/b")));
+    }
+
+    #[test]
+    fn test_rename_errors() {
+        let fs = MemoryFileSystem::with_files(["a/b.txt"]);
+
+        assert!(fs.rename(Path::new("x.txt"), Path::new("y.txt")).is_err());
+        assert!(fs
+            .rename(Path::new("a/b.txt"), Path::new("z/b.txt"))
+            .is_err());
+        assert!(fs.rename(Path::new("a/b.txt"), Path::new("a")).is_err());
+        assert!(fs.rename(Path::new("a"), Path::new("a/c")).is_err());
+    }
+
+    #[test]
+    fn test_identity() {
+        let fs = MemoryFileSystem::with_files(["a.txt"]);
+        let before = fs.metadata(Path::new("a.txt")).unwrap();
+        fs.rename(Path::new("a.txt"), Path::new("b.txt")).unwrap();
+
+        assert_eq!(before, fs.metadata(Path::new("b.txt")).unwrap());
+
+        fs.write(Path::new("b.txt"), b"new");
+        assert!(!before.matches(&fs.metadata(Path::new("b.txt")).unwrap()));
+        assert_eq!(vec![(PathBuf::from("b.txt"), b"new".to_vec())], fs.files());
+    }
+}
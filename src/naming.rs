@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use std::{fs, io, thread};
+
+use anyhow::{bail, Context};
+use chrono::{Local, TimeZone};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::errors::Error;
+use crate::filelist::{FileDirPosition, FileList};
+use crate::filesystem::{absolute_path, get_last_component, parent_dir, split_extension};
+use crate::media::{image_size, is_date_tag, AudioTags, ExifTags, AUDIO_TAGS};
+use crate::template::{format_number, Template};
+use crate::transform::transform_path;
+
+/// Placeholders understood by `--template`.
+pub const PLACEHOLDERS: &[&str] = &[
+    "n", "stem", "ext", "parent", "mtime", "size", "sha256", "exif", "image", "id3",
+];
+
+/// Format of `{mtime}` and EXIF dates without argument.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Parse a `--template`, every placeholder has to be known.
+pub fn name_template(template: &str) -> Result<Template, anyhow::Error> {
+    let template = Template::parse(template)?;
+    template.check_placeholders(PLACEHOLDERS)?;
+
+    Ok(template)
+}
+
+/// Name given by `template` to every entry of `list`, numbered in list order.
+/// The name replaces the last component of the path, `/` adds directories.
+pub fn template_names(
+    list: &FileList,
+    template: &Template,
+    config: &Config,
+) -> Result<HashMap<PathBuf, PathBuf>, anyhow::Error> {
+    // these read every file, only do it when asked for and in parallel
+    let uses = |placeholder: &str| template.placeholders().any(|name| name == placeholder);
+    let hashes = read_files(&list.list, uses("sha256"), hash_file)?;
+    let exif = read_files(&list.list, uses("exif"), |path| Ok(ExifTags::of(path)))?;
+    let sizes = read_files(&list.list, uses("image"), |path| Ok(image_size(path)))?;
+    let audio = read_files(&list.list, uses("id3"), |path| Ok(AudioTags::of(path)))?;
+
+    let mut counters: HashMap<&Path, u64> = HashMap::new();
+    let mut names = HashMap::new();
+    for (i, entry) in list.list.iter().enumerate() {
+        let dir = if config.per_dir {
+            parent_dir(&entry.source)
+        } else {
+            Path::new("")
+        };
+        let index = counters.entry(dir).or_insert(0);
+        let n = config
+            .start
+            .saturating_add(config.step.saturating_mul(*index));
+        *index += 1;
+
+        let file_name = get_last_component(&entry.source);
+        let (stem, ext) = split_extension(file_name);
+        let name = template.render(|name, arg| match name {
+            "n" => format_number(n, arg),
+            "stem" => Ok(stem.to_owned()),
+            "ext" => Ok(ext.to_owned()),
+            "parent" => Ok(parent_name(&entry.source)),
+            "mtime" => format_mtime(entry, arg),
+            "size" => format_number(file_size(entry)?, arg),
+            "sha256" => short_hash(&hashes[i], arg),
+            "exif" => exif_value(entry, exif[i].as_ref(), arg),
+            "image" => image_value(entry, sizes[i], arg),
+            "id3" => audio_value(entry, audio[i].as_ref(), arg),
+            _ => bail!("Unknown placeholder `{{{}}}`", name),
+        })?;
+        if name.is_empty() {
+            bail!("{:?} would be renamed to an empty name", entry.source);
+        }
+
+        names.insert(entry.source.clone(), entry.source.with_file_name(name));
+    }
+
+    Ok(names)
+}
+
+/// Buffer of `list` named by `--template` then rewritten by `--transform`,
+/// ready to edit.
+pub fn rename_buffer(list: &FileList, config: &Config) -> Result<String, Error> {
+    let names = match &config.template {
+        Some(template) => {
+            let template_error = |err: anyhow::Error| Error::Template {
+                template: template.clone(),
+                message: format!("{:#}", err),
+            };
+            let parsed = name_template(template).map_err(template_error)?;
+            template_names(list, &parsed, config).map_err(template_error)?
+        }
+        None => HashMap::new(),
+    };
+
+    let mut renamed = HashMap::new();
+    for entry in list.list.iter() {
+        let name = names.get(&entry.source).unwrap_or(&entry.source);
+        renamed.insert(
+            entry.source.as_path(),
+            transform_path(name, &config.transform)?,
+        );
+    }
+
+    Ok(list.buffer_with(|path| renamed[path].clone()))
+}
+
+/// Modification time of the file in local time, `arg` being a `strftime`
+/// format.
+fn format_mtime(entry: &FileDirPosition, arg: Option<&str>) -> Result<String, anyhow::Error> {
+    let mtime = match entry.fingerprint.and_then(|fingerprint| fingerprint.mtime) {
+        Some(mtime) => mtime,
+        None => fs::metadata(&entry.source)
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("Failed to read the mtime of {:?}", entry.source))?,
+    };
+    let seconds = match mtime.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    };
+    let time = match Local.timestamp_opt(seconds, 0).single() {
+        Some(time) => time,
+        None => bail!("{:?} has an invalid mtime", entry.source),
+    };
+
+    let format = arg.unwrap_or(DEFAULT_DATE_FORMAT);
+    format_date(time.format(format), format)
+}
+
+/// Write a date formatted with `format`, which may be invalid.
+fn format_date(date: impl fmt::Display, format: &str) -> Result<String, anyhow::Error> {
+    let mut output = String::new();
+    if write!(output, "{}", date).is_err() {
+        bail!("Invalid date format `{}`", format);
+    }
+
+    Ok(output)
+}
+
+/// Value of `{exif:<tag>}`, dates being formatted like `{mtime}` as in
+/// `{exif:DateTimeOriginal:%Y}` and replaced by the mtime when missing.
+fn exif_value(
+    entry: &FileDirPosition,
+    tags: Option<&ExifTags>,
+    arg: Option<&str>,
+) -> Result<String, anyhow::Error> {
+    let (tag, format) = match arg.map(|arg| arg.split_once(':')) {
+        None => bail!("`{{exif}}` needs a tag, e.g. `{{exif:Model}}`"),
+        Some(Some((tag, format))) => (tag, Some(format)),
+        Some(None) => (arg.unwrap_or_default(), None),
+    };
+
+    if is_date_tag(tag) {
+        let format = format.unwrap_or(DEFAULT_DATE_FORMAT);
+        return match tags.and_then(|tags| tags.date(tag)) {
+            Some(date) => format_date(date.format(format), format),
+            None => format_mtime(entry, Some(format)),
+        };
+    }
+
+    match tags.and_then(|tags| tags.get(tag)) {
+        Some(value) => name_part(entry, tag, value),
+        None => bail!("{:?} has no EXIF `{}`", entry.source, tag),
+    }
+}
+
+/// A value read from the file made safe to put in its name: separators and
+/// control characters are replaced, and it can't stand for a directory.
+fn name_part(entry: &FileDirPosition, tag: &str, value: &str) -> Result<String, anyhow::Error> {
+    let part: String = value
+        .chars()
+        .map(|c| {
+            if std::path::is_separator(c) || c.is_control() {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    match part.as_str() {
+        "" | "." | ".." => bail!("{:?} has an unusable `{}`: {:?}", entry.source, tag, value),
+        _ => Ok(part),
+    }
+}
+
+/// Value of `{image:width}` or `{image:height}`, in pixels.
+fn image_value(
+    entry: &FileDirPosition,
+    size: Option<(usize, usize)>,
+    arg: Option<&str>,
+) -> Result<String, anyhow::Error> {
+    let (width, height) = match size {
+        Some(size) => size,
+        None => bail!("{:?} is not a known image format", entry.source),
+    };
+
+    match arg {
+        Some("width") => Ok(width.to_string()),
+        Some("height") => Ok(height.to_string()),
+        _ => bail!("Expected `{{image:width}}` or `{{image:height}}`"),
+    }
+}
+
+fn file_size(entry: &FileDirPosition) -> Result<u64, anyhow::Error> {
+    match entry.fingerprint {
+        Some(fingerprint) => Ok(fingerprint.size),
+        None => Ok(fs::metadata(&entry.source)
+            .with_context(|| format!("Failed to read the size of {:?}", entry.source))?
+            .len()),
+    }
+}
+
+/// First `arg` hex digits of a hash, all of them by default.
+fn short_hash(hash: &str, arg: Option<&str>) -> Result<String, anyhow::Error> {
+    let length = match arg {
+        None => hash.len(),
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(length) if length > 0 => length.min(hash.len()),
+            _ => bail!("Invalid hash length `{}`, expected a number of digits", arg),
+        },
+    };
+
+    Ok(hash[..length].to_owned())
+}
+
+/// Value of `{id3:<tag>}`, numbers can be padded like `{n}` as in
+/// `{id3:track:02}`.
+fn audio_value(
+    entry: &FileDirPosition,
+    tags: Option<&AudioTags>,
+    arg: Option<&str>,
+) -> Result<String, anyhow::Error> {
+    let (tag, format) = match arg.map(|arg| arg.split_once(':')) {
+        None => bail!("`{{id3}}` needs a tag, e.g. `{{id3:artist}}`"),
+        Some(Some((tag, format))) => (tag, Some(format)),
+        Some(None) => (arg.unwrap_or_default(), None),
+    };
+    if !AUDIO_TAGS.contains(&tag) {
+        bail!(
+            "Unknown tag `{}`, expected one of {}",
+            tag,
+            AUDIO_TAGS.join(", ")
+        );
+    }
+
+    let value = match tags.and_then(|tags| tags.get(tag)) {
+        Some(value) => value,
+        None => bail!("{:?} has no `{}` tag", entry.source, tag),
+    };
+    match (format, value.parse::<u64>()) {
+        (Some(format), Ok(number)) => format_number(number, Some(format)),
+        (Some(format), Err(_)) => bail!("Cannot format `{}` with `{}`", value, format),
+        (None, _) => name_part(entry, tag, value),
+    }
+}
+
+/// Read something from every entry, spread over the available cores. Nothing
+/// is read unless `used`.
+fn read_files<T, F>(
+    entries: &[FileDirPosition],
+    used: bool,
+    read: F,
+) -> Result<Vec<T>, anyhow::Error>
+where
+    T: Send,
+    F: Fn(&Path) -> Result<T, anyhow::Error> + Sync,
+{
+    if !used {
+        return Ok(Vec::new());
+    }
+
+    let read = &read;
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = entries.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = entries
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|entry| read(&entry.source))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+            })
+            .collect();
+
+        let mut values = Vec::with_capacity(entries.len());
+        for worker in workers {
+            values.extend(worker.join().expect("Reading thread panicked")?);
+        }
+
+        Ok(values)
+    })
+}
+
+/// SHA-256 of a file in hex.
+fn hash_file(path: &Path) -> Result<String, anyhow::Error> {
+    let mut file = fs::File::open(path).with_context(|| format!("Failed to hash {:?}", path))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to hash {:?}", path))?;
+
+    let mut hex = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+
+    Ok(hex)
+}
+
+/// Name of the directory holding `path`, the current one included.
+fn parent_name(path: &Path) -> String {
+    get_last_component(&absolute_path(parent_dir(path))).to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::rename_buffer;
+    use crate::config::Config;
+    use crate::filelist::FileList;
+    use crate::transform::Transform;
+
+    #[test]
+    fn test_template_counters() {
+        let mut list = FileList::new();
+        for path in ["b/x.JPG", "a/z.jpg", "a/y.jpg", "b/w.png"] {
+            list.insert(PathBuf::from(path), 0);
+        }
+        list.enumerate();
+
+        let mut config = Config {
+            template: Some("{parent}-{n:02}{ext}".to_owned()),
+            start: 10,
+            step: 5,
+            ..Config::new()
+        };
+        assert_eq!(
+            "a/a-10.jpg\na/a-15.jpg\nb/b-20.png\nb/b-25.JPG\n",
+            rename_buffer(&list, &config).unwrap()
+        );
+
+        config.per_dir = true;
+        config.transform = vec![Transform::Lower];
+        assert_eq!(
+            "a/a-10.jpg\na/a-15.jpg\nb/b-10.png\nb/b-15.jpg\n",
+            rename_buffer(&list, &config).unwrap()
+        );
+
+        config.template = Some("{name}".to_owned());
+        assert!(rename_buffer(&list, &config).is_err());
+    }
+
+    #[test]
+    fn test_metadata_placeholders() {
+        use std::fs;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let path = tempdir.path().join("notes.txt");
+        fs::write(&path, "abc").unwrap();
+        // the middle of 2021 is in 2021 in every time zone
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_625_097_600);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let mut list = FileList::new();
+        list.insert(path.clone(), 0);
+        list.enumerate();
+
+        let config = Config {
+            template: Some("{mtime:%Y}_{size:04}_{sha256:8}{ext}".to_owned()),
+            ..Config::new()
+        };
+        assert_eq!(
+            format!(
+                "{}\n",
+                tempdir.path().join("2021_0003_ba7816bf.txt").display()
+            ),
+            rename_buffer(&list, &config).unwrap()
+        );
+
+        let config = Config {
+            template: Some("{id3:artist}/{id3:track:02}{ext}".to_owned()),
+            ..Config::new()
+        };
+        assert!(rename_buffer(&list, &config).is_err());
+
+        // no EXIF data, the capture date falls back to the mtime
+        let config = Config {
+            template: Some("{exif:DateTimeOriginal:%Y}{ext}".to_owned()),
+            ..Config::new()
+        };
+        assert_eq!(
+            format!("{}\n", tempdir.path().join("2021.txt").display()),
+            rename_buffer(&list, &config).unwrap()
+        );
+
+        let config = Config {
+            template: Some("{sha256:x}".to_owned()),
+            ..Config::new()
+        };
+        assert!(rename_buffer(&list, &config).is_err());
+    }
+
+    #[test]
+    fn test_exif_values_stay_in_the_name() {
+        use exif::experimental::Writer;
+        use exif::{Field, In, Tag, Value};
+        use std::fs::File;
+
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let photo = |name: &str, model: &[u8]| {
+            let path = tempdir.path().join(name);
+            let field = Field {
+                tag: Tag::Model,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![model.to_vec()]),
+            };
+            let mut writer = Writer::new();
+            writer.push_field(&field);
+            writer
+                .write(&mut File::create(&path).unwrap(), true)
+                .unwrap();
+
+            let mut list = FileList::new();
+            list.insert(path, 0);
+            list.enumerate();
+            list
+        };
+        let config = Config {
+            template: Some("{exif:Model}{ext}".to_owned()),
+            ..Config::new()
+        };
+
+        assert_eq!(
+            format!("{}\n", tempdir.path().join("a-b-c.tif").display()),
+            rename_buffer(&photo("a.tif", b"a/b\nc"), &config).unwrap()
+        );
+        assert!(rename_buffer(&photo("b.tif", b".."), &config).is_err());
+    }
+
+    #[test]
+    fn test_id3_template() {
+        use id3::TagLike;
+        use std::fs;
+
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let song = |name: &str, title: &str| {
+            let path = tempdir.path().join(name);
+            fs::write(&path, b"").unwrap();
+            let mut tag = id3::Tag::new();
+            tag.set_artist("AC/DC");
+            tag.set_album("Back in Black");
+            tag.set_track(3);
+            tag.set_title(title);
+            tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+            let mut list = FileList::new();
+            list.insert(path, 0);
+            list.enumerate();
+            list
+        };
+        let config = Config {
+            template: Some("{id3:artist}/{id3:album}/{id3:track:02} - {id3:title}{ext}".to_owned()),
+            ..Config::new()
+        };
+
+        assert_eq!(
+            format!(
+                "{}\n",
+                tempdir
+                    .path()
+                    .join("AC-DC/Back in Black/03 - Hells Bells.mp3")
+                    .display()
+            ),
+            rename_buffer(&song("a.mp3", "Hells Bells"), &config).unwrap()
+        );
+        assert!(rename_buffer(&song("b.mp3", ".."), &config).is_err());
+    }
+}
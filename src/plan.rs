@@ -0,0 +1,352 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, ConflictStrategy};
+use crate::errors::Error;
+use crate::exec::Activity;
+use crate::filelist::FileList;
+use crate::filesystem::{case_fold, parent_dir, temp_name, FileSystem};
+
+/// Remembers which directories are case-insensitive, probing each one once.
+pub struct CaseSensitivity<'a> {
+    fs: &'a dyn FileSystem,
+    forced: bool,
+    dirs: HashMap<PathBuf, bool>,
+}
+
+impl<'a> CaseSensitivity<'a> {
+    pub fn new(fs: &'a dyn FileSystem, config: &Config) -> Self {
+        Self {
+            fs,
+            forced: config.case_insensitive,
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// Wether the directory holding `path` is case-insensitive
+    pub fn is_insensitive(&mut self, path: &Path) -> bool {
+        if self.forced {
+            return true;
+        }
+
+        let dir = parent_dir(path);
+        *self
+            .dirs
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| self.fs.is_case_insensitive(dir))
+    }
+
+    /// Key telling `path` apart from the others of its directory, folded
+    /// where case does not matter.
+    pub fn key(&mut self, path: &Path) -> String {
+        if self.is_insensitive(path) {
+            case_fold(path)
+        } else {
+            path.to_string_lossy().into_owned()
+        }
+    }
+}
+
+/// Check the plan as a whole before anything runs, reporting every collision
+/// between its entries with the buffer lines involved.
+pub fn validate_plan(
+    modified: &FileList,
+    activities: &[Activity],
+    case_sensitivity: &mut CaseSensitivity,
+) -> Result<(), Error> {
+    let mut problems = Vec::new();
+
+    // names that only differ by case are the same file on these filesystems
+    let mut folded: HashMap<String, (usize, &PathBuf)> = HashMap::new();
+    for file in modified.list.iter() {
+        if !case_sensitivity.is_insensitive(&file.source) {
+            continue;
+        }
+
+        if let Some((line, other)) =
+            folded.insert(case_fold(&file.source), (file.line, &file.source))
+        {
+            problems.push(Error::CaseCollision {
+                line: file.line,
+                other_line: line,
+                path: file.source.clone(),
+                other: other.clone(),
+            });
+        }
+    }
+
+    let moved: Vec<&Activity> = activities
+        .iter()
+        .filter(|activity| activity.on_conflict != Some(ConflictStrategy::Skip))
+        .filter(|activity| !activity.delete)
+        .collect();
+
+    let mut destinations: HashMap<&PathBuf, usize> = HashMap::new();
+    for activity in moved.iter() {
+        if let Some(line) = destinations.insert(&activity.destination, activity.line) {
+            problems.push(Error::SameDestination {
+                line: activity.line,
+                other_line: line,
+                to: activity.destination.clone(),
+            });
+        }
+    }
+
+    // a destination taken by a source counts as free only if that source moves
+    let sources: HashMap<&PathBuf, &Activity> = activities
+        .iter()
+        .map(|activity| (&activity.source, activity))
+        .collect();
+    for activity in moved.iter() {
+        if let Some(other) = sources.get(&activity.destination) {
+            if other.on_conflict == Some(ConflictStrategy::Skip) && !replaces_target(activity) {
+                problems.push(Error::SkippedTarget {
+                    line: activity.line,
+                    other_line: other.line,
+                    from: activity.source.clone(),
+                    to: other.source.clone(),
+                });
+            }
+        }
+    }
+
+    // trashing a directory takes along everything below it
+    let deleted: HashMap<&Path, &Activity> = activities
+        .iter()
+        .filter(|activity| activity.delete)
+        .map(|activity| (activity.source.as_path(), activity))
+        .collect();
+    if !deleted.is_empty() {
+        for activity in activities.iter().filter(|activity| !activity.delete) {
+            let paths = [&activity.source, &activity.destination];
+            let inside = paths.iter().find_map(|path| {
+                path.ancestors()
+                    .skip(1)
+                    .find_map(|dir| deleted.get(dir))
+                    .map(|dir| (path, dir))
+            });
+
+            if let Some((path, dir)) = inside {
+                problems.push(Error::InsideDeleted {
+                    line: activity.line,
+                    other_line: dir.line,
+                    path: path.to_path_buf(),
+                    deleted: dir.source.clone(),
+                });
+            }
+        }
+    }
+
+    Error::from_list(problems)
+}
+
+fn replaces_target(activity: &Activity) -> bool {
+    matches!(
+        activity.on_conflict,
+        Some(ConflictStrategy::Overwrite | ConflictStrategy::Backup)
+    )
+}
+
+/// Group of each activity, linking a rename to the one freeing its
+/// destination. Chains and cycles, with the hops through a temporary name,
+/// have to run or be skipped as a whole.
+pub fn linked_groups(activities: &[Activity]) -> Vec<usize> {
+    let mut groups: Vec<usize> = (0..activities.len()).collect();
+    fn root(groups: &mut [usize], mut i: usize) -> usize {
+        while groups[i] != i {
+            groups[i] = groups[groups[i]];
+            i = groups[i];
+        }
+        i
+    }
+
+    let by_source: HashMap<&PathBuf, usize> = activities
+        .iter()
+        .enumerate()
+        .map(|(i, activity)| (&activity.source, i))
+        .collect();
+    for (i, activity) in activities.iter().enumerate() {
+        if let Some(&j) = by_source.get(&activity.destination) {
+            let (a, b) = (root(&mut groups, i), root(&mut groups, j));
+            groups[a] = b;
+        }
+    }
+
+    (0..activities.len())
+        .map(|i| root(&mut groups, i))
+        .collect()
+}
+
+/// Indexes of the moves to a temporary name in ordered activities, the only
+/// renames whose destination is the source of a later activity.
+pub fn temp_moves(activities: &[Activity]) -> HashSet<usize> {
+    let last_source: HashMap<&PathBuf, usize> = activities
+        .iter()
+        .enumerate()
+        .map(|(i, activity)| (&activity.source, i))
+        .collect();
+
+    activities
+        .iter()
+        .enumerate()
+        .filter(|(i, activity)| {
+            activity.is_rename() && last_source.get(&activity.destination) > Some(i)
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Order the activities so that each rename runs after the one freeing its
+/// destination, cycles such as swaps go through a temporary name.
+pub fn order_activities(fs: &dyn FileSystem, activities: Vec<Activity>) -> Vec<Activity> {
+    let mut activities: Vec<Option<Activity>> = activities.into_iter().map(Some).collect();
+
+    let by_source: HashMap<PathBuf, usize> = activities
+        .iter()
+        .enumerate()
+        .filter_map(|(i, activity)| activity.as_ref().map(|a| (a.source.clone(), i)))
+        .collect();
+
+    // the activity that has to run before `i`, as it moves away its destination
+    let blocker = |activities: &Vec<Option<Activity>>, i: usize| -> Option<usize> {
+        let activity = activities[i].as_ref()?;
+        if activity.case_only || activity.on_conflict == Some(ConflictStrategy::Skip) {
+            return None;
+        }
+
+        by_source
+            .get(&activity.destination)
+            .copied()
+            .filter(|&j| j != i && activities[j].is_some())
+    };
+
+    let mut ordered = Vec::with_capacity(activities.len());
+    for start in 0..activities.len() {
+        if activities[start].is_none() {
+            continue;
+        }
+
+        // follow the chain of blockers until a free destination or a cycle
+        let mut chain = vec![start];
+        let mut cycle_start = None;
+        while let Some(next) = blocker(&activities, *chain.last().unwrap()) {
+            if let Some(position) = chain.iter().position(|&i| i == next) {
+                cycle_start = Some(position);
+                break;
+            }
+            chain.push(next);
+        }
+
+        if let Some(position) = cycle_start {
+            let head = activities[chain[position]].as_mut().unwrap();
+            let temp = temp_name(fs, &head.source);
+            let to_temp = Activity {
+                mkdirs: Vec::new(),
+                source: head.source.clone(),
+                given_destination_path: temp.clone(),
+                destination: temp.clone(),
+                line: head.line,
+                case_only: false,
+                on_conflict: None,
+                backup: None,
+                fingerprint: head.fingerprint,
+                symlinks: Vec::new(),
+                references: Vec::new(),
+                changes: Vec::new(),
+                delete: false,
+            };
+            head.source = temp;
+            ordered.push(to_temp);
+        }
+
+        for i in chain.into_iter().rev() {
+            if let Some(activity) = activities[i].take() {
+                ordered.push(activity);
+            }
+        }
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{order_activities, temp_moves};
+    use crate::exec::Activity;
+    use crate::memory::MemoryFileSystem;
+
+    fn activity(source: &str, destination: &str, line: usize) -> Activity {
+        Activity {
+            mkdirs: Vec::new(),
+            source: PathBuf::from(source),
+            given_destination_path: PathBuf::from(destination),
+            destination: PathBuf::from(destination),
+            line,
+            case_only: false,
+            on_conflict: None,
+            backup: None,
+            fingerprint: None,
+            symlinks: Vec::new(),
+            references: Vec::new(),
+            changes: Vec::new(),
+            delete: false,
+        }
+    }
+
+    fn steps(activities: &[Activity]) -> Vec<(PathBuf, PathBuf)> {
+        activities
+            .iter()
+            .map(|activity| (activity.source.clone(), activity.destination.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_order_chain() {
+        let ordered = order_activities(
+            &MemoryFileSystem::new(),
+            vec![
+                activity("a", "b", 1),
+                activity("b", "c", 2),
+                activity("c", "d", 3),
+            ],
+        );
+
+        assert_eq!(
+            vec![
+                (PathBuf::from("c"), PathBuf::from("d")),
+                (PathBuf::from("b"), PathBuf::from("c")),
+                (PathBuf::from("a"), PathBuf::from("b")),
+            ],
+            steps(&ordered)
+        );
+    }
+
+    #[test]
+    fn test_order_swap() {
+        let ordered = order_activities(
+            &MemoryFileSystem::new(),
+            vec![activity("a", "b", 1), activity("b", "a", 2)],
+        );
+        let temp = ordered[0].destination.clone();
+        assert!(temp
+            .parent()
+            .and_then(|dir| dir.to_str())
+            .is_some_and(|dir| dir.starts_with(".rnr-buf-")));
+        assert_eq!(Some("a".as_ref()), temp.file_name());
+        assert_eq!(
+            vec![0],
+            temp_moves(&ordered).into_iter().collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            vec![
+                (PathBuf::from("a"), temp.clone()),
+                (PathBuf::from("b"), PathBuf::from("a")),
+                (temp, PathBuf::from("b")),
+            ],
+            steps(&ordered)
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::columns::AttributeChange;
+use crate::config::{Config, ConflictStrategy};
+use crate::errors::Error;
+use crate::exec::{finish_plan, Activity};
+use crate::filelist::{FileList, Fingerprint};
+use crate::filesystem::{backup_name, missing_dirs, FileSystem};
+use crate::plan::{temp_moves, CaseSensitivity};
+
+/// Version of the plan file format, bumped on incompatible changes.
+pub const PLAN_VERSION: u32 = 2;
+
+/// Operations written by `rnr-buf plan`, run later by `rnr-buf apply`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanFile {
+    pub version: u32,
+    /// Absolute directory the plan was made in, relative paths start from it.
+    pub root: PathBuf,
+    pub operations: Vec<Operation>,
+}
+
+/// A rename, deletion or attribute change of a plan file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Operation {
+    /// Line of the edited buffer the operation comes from.
+    pub line: usize,
+    pub source: PathBuf,
+    /// Same as the source for deletions and attribute changes.
+    pub destination: PathBuf,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub delete: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub case_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_conflict: Option<ConflictStrategy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<AttributeChange>,
+    /// State of the source when it was listed, its size and mtime are
+    /// checked again when applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl PlanFile {
+    /// Record planned activities, with paths relative to `root`. Skipped ones
+    /// are left out and detours through temporary names folded back, `apply`
+    /// orders them again.
+    pub fn from_activities(activities: &[Activity], root: &Path) -> Self {
+        let temp_moves = temp_moves(activities);
+        let mut operations: Vec<Operation> = Vec::new();
+        let mut temps: HashMap<&PathBuf, usize> = HashMap::new();
+        for (i, activity) in activities.iter().enumerate() {
+            if activity.on_conflict == Some(ConflictStrategy::Skip) {
+                continue;
+            }
+
+            if let Some(index) = temps.remove(&activity.source) {
+                let operation = &mut operations[index];
+                operation.destination = activity.destination.clone();
+                operation.on_conflict = activity.on_conflict;
+                operation.changes = activity.changes.clone();
+                continue;
+            }
+
+            if temp_moves.contains(&i) {
+                temps.insert(&activity.destination, operations.len());
+            }
+
+            operations.push(Operation {
+                line: activity.line,
+                source: activity.source.clone(),
+                destination: activity.destination.clone(),
+                delete: activity.delete,
+                case_only: activity.case_only,
+                on_conflict: activity.on_conflict,
+                changes: activity.changes.clone(),
+                fingerprint: activity.fingerprint,
+            });
+        }
+
+        Self {
+            version: PLAN_VERSION,
+            root: root.to_path_buf(),
+            operations,
+        }
+    }
+
+    /// Write the plan as JSON.
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content + "\n").with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    /// Read a plan written by [`PlanFile::save`].
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        let plan: Self = serde_json::from_str(&content)
+            .with_context(|| format!("{:?} is not a rnr-buf plan", path))?;
+        if plan.version != PLAN_VERSION {
+            bail!(
+                "{:?} is a version {} plan, only version {} is supported",
+                path,
+                plan.version,
+                PLAN_VERSION
+            );
+        }
+
+        Ok(plan)
+    }
+
+    /// Check every operation again against the tree as it is now, the same
+    /// way a fresh plan is, and turn them into the activities to run. Paths
+    /// are resolved against the root of the plan, not the current dir.
+    pub fn activities(&self, fs: &dyn FileSystem, config: &Config) -> Result<Vec<Activity>, Error> {
+        let mut case_sensitivity = CaseSensitivity::new(fs, config);
+        let operations: Vec<Operation> = self
+            .operations
+            .iter()
+            .map(|operation| Operation {
+                source: self.root.join(&operation.source),
+                destination: self.root.join(&operation.destination),
+                ..operation.clone()
+            })
+            .collect();
+
+        // sources moved or deleted by the plan free their path for other entries
+        let vacated: HashSet<String> = operations
+            .iter()
+            .filter(|operation| operation.delete || operation.source != operation.destination)
+            .map(|operation| case_sensitivity.key(&operation.source))
+            .collect();
+        let occupied = |destination: &PathBuf, case_sensitivity: &mut CaseSensitivity| {
+            fs.exists(destination) && !vacated.contains(&case_sensitivity.key(destination))
+        };
+
+        let mut problems = Vec::new();
+        let mut outcome = Vec::new();
+        let mut destinations = FileList::new();
+        for operation in operations.iter() {
+            let mut activity = Activity {
+                mkdirs: Vec::new(),
+                source: operation.source.clone(),
+                given_destination_path: operation.destination.clone(),
+                destination: operation.destination.clone(),
+                line: operation.line,
+                case_only: operation.case_only,
+                on_conflict: operation.on_conflict,
+                backup: None,
+                fingerprint: operation.fingerprint,
+                symlinks: Vec::new(),
+                references: Vec::new(),
+                changes: operation.changes.clone(),
+                delete: operation.delete,
+            };
+
+            let changed = match fs.metadata(&activity.source) {
+                Err(_) => Some(Error::SourceVanished {
+                    line: activity.line,
+                    from: activity.source.clone(),
+                }),
+                Ok(current)
+                    if activity
+                        .fingerprint
+                        .is_some_and(|f| !f.matches_content(&current)) =>
+                {
+                    Some(Error::SourceChanged {
+                        line: activity.line,
+                        from: activity.source.clone(),
+                    })
+                }
+                // the run itself checks for changes made since now
+                Ok(current) => {
+                    activity.fingerprint = Some(current);
+                    None
+                }
+            };
+            if let Some(err) = changed {
+                if !config.skip_changed {
+                    problems.push(err);
+                } else if !config.quiet {
+                    eprintln!("{}, skipped", err);
+                }
+                continue;
+            }
+
+            if activity.is_rename() && !activity.case_only {
+                if occupied(&activity.destination, &mut case_sensitivity) {
+                    match activity.on_conflict {
+                        Some(ConflictStrategy::Overwrite) => {}
+                        Some(ConflictStrategy::Backup) => {
+                            activity.backup = Some(backup_name(fs, &activity.destination))
+                        }
+                        _ => problems.push(Error::TargetAppeared {
+                            line: activity.line,
+                            from: activity.source.clone(),
+                            to: activity.destination.clone(),
+                        }),
+                    }
+                }
+
+                activity.mkdirs = missing_dirs(fs, &activity.destination);
+                if !activity.mkdirs.is_empty() && !config.mkdir {
+                    problems.push(Error::MissingDir {
+                        line: activity.line,
+                        from: activity.source.clone(),
+                        to: activity.destination.clone(),
+                    });
+                }
+            }
+
+            if !activity.delete {
+                let position = destinations.list.len();
+                destinations.insert(activity.destination.clone(), position);
+                if let Some(entry) = destinations.list.last_mut() {
+                    entry.line = activity.line;
+                }
+            }
+            outcome.push(activity);
+        }
+        Error::from_list(problems)?;
+
+        finish_plan(fs, &destinations, outcome, config, &mut case_sensitivity)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::PlanFile;
+    use crate::config::Config;
+    use crate::errors::Error;
+    use crate::exec::{batch_operations, perfom_operations};
+    use crate::filelist::FileList;
+    use crate::filesystem::FileSystem;
+    use crate::memory::MemoryFileSystem;
+
+    #[test]
+    fn test_swap_round_trip() {
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt", "c.txt"]);
+        let before_list = FileList::new_from_raw("a.txt\nb.txt\nc.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("b.txt\na.txt\nd.txt\n".to_owned()).unwrap();
+        let config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        let plan = PlanFile::from_activities(&outcome, Path::new(""));
+        let renames: Vec<(&str, &str)> = plan
+            .operations
+            .iter()
+            .map(|operation| {
+                (
+                    operation.source.to_str().unwrap(),
+                    operation.destination.to_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![("a.txt", "b.txt"), ("b.txt", "a.txt"), ("c.txt", "d.txt")],
+            renames
+        );
+
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let path = tempdir.path().join("plan.json");
+        plan.save(&path).unwrap();
+        let loaded = PlanFile::load(&path).unwrap();
+        assert_eq!(plan, loaded);
+
+        let activities = loaded.activities(&fs, &config).unwrap();
+        perfom_operations(&fs, &activities, &config).unwrap();
+        assert_eq!(Some(b"a.txt".to_vec()), fs.read(Path::new("b.txt")));
+        assert_eq!(Some(b"b.txt".to_vec()), fs.read(Path::new("a.txt")));
+        assert_eq!(Some(b"c.txt".to_vec()), fs.read(Path::new("d.txt")));
+    }
+
+    #[test]
+    fn test_apply_target_vacated_by_other_case() {
+        let fs = MemoryFileSystem::case_insensitive();
+        for name in ["A.TXT", "c.txt"] {
+            fs.write(Path::new(name), name.as_bytes());
+        }
+        let before_list = FileList::new_from_raw("A.TXT\nc.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("b.txt\na.txt\n".to_owned()).unwrap();
+        let config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        let plan = PlanFile::from_activities(&outcome, Path::new(""));
+        let activities = plan.activities(&fs, &config).unwrap();
+        perfom_operations(&fs, &activities, &config).unwrap();
+        assert_eq!(Some(b"A.TXT".to_vec()), fs.read(Path::new("b.txt")));
+        assert_eq!(Some(b"c.txt".to_vec()), fs.read(Path::new("a.txt")));
+    }
+
+    #[test]
+    fn test_apply_checks_the_tree_again() {
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt"]);
+        let before_list = FileList::new_from_raw("a.txt\nb.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("c.txt\nd.txt\n".to_owned()).unwrap();
+        let mut config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        let plan = PlanFile::from_activities(&outcome, Path::new(""));
+
+        fs.write(Path::new("c.txt"), b"c");
+        fs.remove_file(Path::new("b.txt")).unwrap();
+        let err = plan.activities(&fs, &config).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Multiple(ref errors) if matches!(
+                errors.as_slice(),
+                [Error::TargetAppeared { line: 1, .. }, Error::SourceVanished { line: 2, .. }]
+            )
+        ));
+
+        // the vanished source is skipped, the appeared target still fails
+        config.skip_changed = true;
+        assert!(matches!(
+            plan.activities(&fs, &config),
+            Err(Error::TargetAppeared { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_from_another_dir() {
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt"]);
+        let before_list = FileList::new_from_raw("a.txt\nb.txt\n".to_owned()).unwrap();
+        let after_list = FileList::new_from_raw("c.txt\nb.txt\n".to_owned()).unwrap();
+        let config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let outcome = batch_operations(&fs, &before_list, &after_list, &config).unwrap();
+        let plan = PlanFile::from_activities(&outcome, Path::new("/w"));
+        assert_eq!(Path::new("a.txt"), plan.operations[0].source);
+
+        // the same tree elsewhere, with fresh identities but the same content
+        let moved = MemoryFileSystem::new();
+        let mtime = fs.metadata(Path::new("a.txt")).unwrap().mtime.unwrap();
+        moved.write(Path::new("/w/a.txt"), b"a.txt");
+        moved.set_mtime(Path::new("/w/a.txt"), mtime).unwrap();
+
+        let activities = plan.activities(&moved, &config).unwrap();
+        perfom_operations(&moved, &activities, &config).unwrap();
+        assert_eq!(Some(b"a.txt".to_vec()), moved.read(Path::new("/w/c.txt")));
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, process};
+
+use anyhow::{bail, Context};
+
+use crate::columns::SEPARATOR;
+use crate::config::Config;
+use crate::errors::error_string;
+use crate::exec::{Activity, BUFFER_SUFFIX};
+use crate::filelist::{FileList, COMMENT};
+
+/// Prefix of the lines holding the original listing in a saved buffer.
+pub const ORIGINAL_PREFIX: &str = "#: ";
+
+/// Where failed buffers are kept, `$XDG_STATE_HOME/rnr-buf` by default.
+pub fn state_dir() -> PathBuf {
+    let state_home = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local").join("state"),
+            None => env::temp_dir(),
+        },
+    };
+
+    state_home.join("rnr-buf")
+}
+
+/// Annotation added on top of a resumed buffer.
+fn error_annotation(err: &anyhow::Error) -> String {
+    let mut annotation = String::new();
+    for line in format!("{:#}", err).lines() {
+        annotation += &format!("{} {}{}\n", COMMENT, error_string(), line);
+    }
+
+    annotation
+}
+
+/// Lines written by rnr-buf itself, dropped when a buffer is saved or resumed.
+fn is_annotation(line: &str) -> bool {
+    line.starts_with(ORIGINAL_PREFIX)
+        || line.starts_with(&format!("{} {}", COMMENT, error_string()))
+}
+
+/// Save the edited buffer along with the original listing and the error.
+pub fn save_buffer(
+    dir: &Path,
+    original: &FileList,
+    edited: &str,
+    err: &anyhow::Error,
+) -> Result<PathBuf, anyhow::Error> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let path = dir.join(format!(
+        "buffer-{}-{}{}",
+        timestamp,
+        process::id(),
+        BUFFER_SUFFIX
+    ));
+
+    let mut content = error_annotation(err);
+    for line in original.raw.lines() {
+        content += &format!("{}{}\n", ORIGINAL_PREFIX, line);
+    }
+    for line in edited.lines().filter(|line| !is_annotation(line)) {
+        content += line;
+        content.push('\n');
+    }
+
+    fs::write(&path, content).with_context(|| format!("Failed to write {:?}", path))?;
+
+    Ok(path)
+}
+
+/// Bring a run that failed halfway back in line with the tree: the entries
+/// moved by the `completed` activities are listed where they are now, and the
+/// deleted ones are left out of both the listing and the edited buffer, so
+/// that resuming only runs what is left.
+pub fn settle(
+    original: &FileList,
+    edited: &str,
+    activities: &[Activity],
+    completed: &[usize],
+) -> (FileList, String) {
+    // where each listed entry is now, by the path it was listed as
+    let mut current: HashMap<PathBuf, &Path> = original
+        .list
+        .iter()
+        .map(|entry| (entry.source.clone(), entry.source.as_path()))
+        .collect();
+    let mut moved: HashMap<&Path, (PathBuf, usize)> = HashMap::new();
+    let mut deleted = HashSet::new();
+    let mut dropped_lines = HashSet::new();
+    for activity in completed.iter().map(|&i| &activities[i]) {
+        let listed = match current.remove(&activity.source) {
+            Some(listed) => listed,
+            None => continue,
+        };
+
+        if activity.delete {
+            deleted.insert(listed);
+            dropped_lines.insert(activity.line);
+        } else {
+            current.insert(activity.destination.clone(), listed);
+            moved.insert(listed, (activity.destination.clone(), activity.line));
+        }
+    }
+
+    let listing = original.relisted(|entry| match moved.get(entry.source.as_path()) {
+        _ if deleted.contains(entry.source.as_path()) => None,
+        Some((destination, _)) => Some(destination.clone()),
+        None => Some(entry.source.clone()),
+    });
+
+    // the left column of a two-column buffer names the entry as listed
+    let origins: HashMap<usize, (&Path, &PathBuf)> = moved
+        .iter()
+        .filter(|_| original.two_column)
+        .map(|(listed, (destination, line))| (*line, (*listed, destination)))
+        .collect();
+    let mut buffer = String::new();
+    for (i, line) in edited.lines().enumerate() {
+        if dropped_lines.contains(&(i + 1)) {
+            continue;
+        }
+
+        match origins.get(&(i + 1)) {
+            Some((listed, destination)) => {
+                buffer += &line.replacen(
+                    &format!("{}{}", listed.display(), SEPARATOR),
+                    &format!("{}{}", destination.display(), SEPARATOR),
+                    1,
+                )
+            }
+            None => buffer += line,
+        }
+        buffer.push('\n');
+    }
+
+    (listing, buffer)
+}
+
+/// Read a saved buffer back, returning the original listing and the buffer to
+/// edit, annotated with the error that made the previous run fail.
+pub fn load_buffer(path: &Path, config: &Config) -> Result<(FileList, String), anyhow::Error> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+
+    let mut original = String::new();
+    let mut annotations = String::new();
+    let mut edited = String::new();
+    for line in content.lines() {
+        if let Some(source) = line.strip_prefix(ORIGINAL_PREFIX) {
+            original += source;
+            original.push('\n');
+        } else if is_annotation(line) {
+            annotations += line;
+            annotations.push('\n');
+        } else {
+            edited += line;
+            edited.push('\n');
+        }
+    }
+
+    if original.is_empty() {
+        bail!("{:?} is not a saved rnr-buf buffer", path);
+    }
+
+    Ok((
+        crate::parse_buffer_with(&original, None, config)?,
+        annotations + &edited,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use anyhow::anyhow;
+
+    use super::{load_buffer, save_buffer, settle};
+    use crate::config::Config;
+    use crate::exec::{batch_operations, perfom_operations, perfom_operations_tracked};
+    use crate::filelist::FileList;
+    use crate::filesystem::FileSystem;
+    use crate::memory::MemoryFileSystem;
+
+    #[test]
+    fn test_save_and_resume() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let mut original = FileList::new();
+        original.insert(PathBuf::from("tmp/file_1.txt"), 0);
+        original.insert(PathBuf::from("tmp/file_2.txt"), 0);
+        original.enumerate();
+
+        let edited = "tmp/file_10.txt\ntmp/file_20.txt\n";
+        let path = save_buffer(tempdir.path(), &original, edited, &anyhow!("A file exists"))
+            .expect("Failed to save buffer");
+
+        let (resumed, buffer) = load_buffer(&path, &Config::new()).expect("Failed to load buffer");
+
+        assert_eq!(original, resumed);
+        assert_eq!(
+            "# [rnr-buf error]: A file exists\ntmp/file_10.txt\ntmp/file_20.txt\n",
+            buffer
+        );
+        assert_eq!(2, FileList::new_from_raw(buffer).unwrap().list.len());
+    }
+
+    #[test]
+    fn test_settle_after_a_partial_run() {
+        let fs = MemoryFileSystem::with_files(["a.txt", "b.txt", "c.txt"]);
+        let original = FileList::new_from_raw("a.txt\nb.txt\nc.txt\n".to_owned()).unwrap();
+        let edited = "\nx.txt\nd/y.txt\n";
+        let modified = FileList::new_from_raw(edited.to_owned()).unwrap();
+        let config = Config {
+            delete: true,
+            mkdir: true,
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        // the last rename fails once the first two have run
+        let outcome = batch_operations(&fs, &original, &modified, &config).unwrap();
+        fs.write(&PathBuf::from("d"), b"d");
+        let mut completed = Vec::new();
+        assert!(perfom_operations_tracked(&fs, &outcome, &config, &mut completed).is_err());
+        assert_eq!(2, completed.len());
+
+        let (original, edited) = settle(&original, edited, &outcome, &completed);
+        assert_eq!("x.txt\nc.txt\n", original.raw);
+        assert_eq!("x.txt\nd/y.txt\n", edited);
+
+        fs.remove_file(&PathBuf::from("d")).unwrap();
+        let modified = FileList::new_from_raw(edited).unwrap();
+        let outcome = batch_operations(&fs, &original, &modified, &config).unwrap();
+        perfom_operations(&fs, &outcome, &config).unwrap();
+        assert_eq!(
+            vec![
+                PathBuf::from(".Trash/files/a.txt"),
+                PathBuf::from("d/y.txt"),
+                PathBuf::from("x.txt")
+            ],
+            fs.files()
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>()
+        );
+    }
+}
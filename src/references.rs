@@ -0,0 +1,477 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use aho_corasick::AhoCorasick;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::config::ConflictStrategy;
+use crate::errors::Error;
+use crate::exec::Activity;
+use crate::filesystem::{absolute_path, get_last_component, EntryKind, FileSystem};
+use crate::symlinks::{moved_path, relative_path};
+
+/// How a reference is told apart from a longer word around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    /// A path, which may follow a `./`.
+    Path,
+    /// A bare file name, which may not follow a dir.
+    Name,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    pub old: String,
+    pub new: String,
+    pub kind: ReferenceKind,
+}
+
+/// References to the source of an activity found in a text file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceUpdate {
+    /// The text file, where it is after the plan has run.
+    pub file: PathBuf,
+    pub replacements: Vec<Replacement>,
+    /// Number, content before and after of the changed lines, for the preview.
+    pub lines: Vec<(usize, String, String)>,
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Whether `old` found at `start` of `text` is a whole reference
+fn is_reference(text: &str, start: usize, replacement: &Replacement) -> bool {
+    let before = &text[..start];
+    let accepted_before = match before.chars().next_back() {
+        None => true,
+        Some('/') => {
+            replacement.kind == ReferenceKind::Path
+                && !replacement.old.starts_with('/')
+                && before.ends_with("./")
+                && !before.ends_with("../")
+        }
+        Some(c) => !is_word(c) && c != '.',
+    };
+
+    let mut after = text[start + replacement.old.len()..].chars();
+    let accepted_after = match after.next() {
+        None => true,
+        // the extension goes on, e.g. `a.txt.bak`
+        Some('.') => !after.next().is_some_and(is_word),
+        Some(c) => !is_word(c),
+    };
+
+    accepted_before && accepted_after
+}
+
+/// One automaton looking for every pattern at once, failing past the
+/// limits of its state ids.
+fn automaton<P: AsRef<[u8]>>(patterns: &[P]) -> Result<AhoCorasick, Error> {
+    AhoCorasick::new(patterns).map_err(|err| Error::TooManyReferences {
+        count: patterns.len(),
+        message: err.to_string(),
+    })
+}
+
+fn matcher(replacements: &[&Replacement]) -> Result<AhoCorasick, Error> {
+    let patterns: Vec<&str> = replacements
+        .iter()
+        .map(|replacement| replacement.old.as_str())
+        .collect();
+
+    automaton(&patterns)
+}
+
+/// Whole references of `text`, as the range and index of their replacement.
+/// The longest one wins where several start at the same place.
+fn find_references(
+    text: &str,
+    replacements: &[&Replacement],
+    matcher: &AhoCorasick,
+) -> Vec<(usize, usize, usize)> {
+    let mut found: Vec<(usize, usize, usize)> = matcher
+        .find_overlapping_iter(text)
+        .map(|found| (found.start(), found.end(), found.pattern().as_usize()))
+        .filter(|&(start, _, index)| is_reference(text, start, replacements[index]))
+        .collect();
+    found.sort_by_key(|&(start, end, _)| (start, std::cmp::Reverse(end)));
+
+    let mut end = 0;
+    found.retain(|&(start, found_end, _)| {
+        let kept = start >= end;
+        if kept {
+            end = found_end;
+        }
+        kept
+    });
+
+    found
+}
+
+/// Replace the given references of `text`.
+fn apply_references(
+    text: &str,
+    replacements: &[&Replacement],
+    found: &[(usize, usize, usize)],
+) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut index = 0;
+    for &(start, end, replacement) in found {
+        replaced.push_str(&text[index..start]);
+        replaced.push_str(&replacements[replacement].new);
+        index = end;
+    }
+    replaced.push_str(&text[index..]);
+
+    replaced
+}
+
+/// Replace every whole reference in `text`, longer ones first.
+pub fn replace_references(text: &str, replacements: &[&Replacement]) -> Result<String, Error> {
+    let found = find_references(text, replacements, &matcher(replacements)?);
+
+    Ok(apply_references(text, replacements, &found))
+}
+
+fn glob_set(globs: &[String]) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).map_err(|err| Error::Glob {
+            glob: glob.clone(),
+            message: err.kind().to_string(),
+        })?);
+    }
+
+    builder.build().map_err(|err| Error::Glob {
+        glob: globs.join(" "),
+        message: err.to_string(),
+    })
+}
+
+/// Ways the source of an activity may be written in `file`, absolute, as
+/// given in the buffer, relative to the file, and by name when the file is
+/// next to it.
+fn replacements(activity: &Activity, file_dir: &Path, moved_dir: &Path) -> Vec<Replacement> {
+    let (source, destination) = (
+        absolute_path(&activity.source),
+        absolute_path(&activity.destination),
+    );
+    let clean = |path: &Path| path.strip_prefix(".").unwrap_or(path).to_path_buf();
+
+    let mut paths = vec![(source.clone(), destination.clone())];
+    if activity.source.is_relative() {
+        paths.push((clean(&activity.source), clean(&activity.destination)));
+    }
+    paths.push((
+        relative_path(file_dir, &source),
+        relative_path(moved_dir, &destination),
+    ));
+
+    let mut replacements: Vec<Replacement> = Vec::new();
+    let mut add = |old: String, new: String, kind: ReferenceKind| {
+        if old != new && !old.is_empty() && !replacements.iter().any(|r| r.old == old) {
+            replacements.push(Replacement { old, new, kind });
+        }
+    };
+    for (old, new) in paths {
+        add(
+            old.to_string_lossy().into_owned(),
+            new.to_string_lossy().into_owned(),
+            ReferenceKind::Path,
+        );
+    }
+    // a bare name may be any file of that name elsewhere
+    if source.parent() == Some(file_dir) {
+        add(
+            get_last_component(&source).to_owned(),
+            get_last_component(&destination).to_owned(),
+            ReferenceKind::Name,
+        );
+    }
+
+    replacements
+}
+
+/// Attach to the activities the references to their source found in the
+/// text files below `root` matching `globs`. Files that are not UTF-8 are
+/// left alone.
+pub fn find_reference_updates(
+    fs: &dyn FileSystem,
+    root: &Path,
+    globs: &[String],
+    activities: &mut [Activity],
+) -> Result<(), Error> {
+    let globs = glob_set(globs)?;
+    let moves: HashMap<PathBuf, (PathBuf, usize)> = activities
+        .iter()
+        .enumerate()
+        .filter(|(_, activity)| activity.on_conflict != Some(ConflictStrategy::Skip))
+        .filter(|(_, activity)| activity.is_rename())
+        .map(|(index, activity)| {
+            (
+                absolute_path(&activity.source),
+                (absolute_path(&activity.destination), index),
+            )
+        })
+        .collect();
+
+    // every way of writing a source ends with its name, files without any of
+    // them are skipped at once
+    let mut named: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (source, (_, index)) in moves.iter() {
+        named
+            .entry(get_last_component(source))
+            .or_default()
+            .push(*index);
+    }
+    let (names, named): (Vec<&str>, Vec<Vec<usize>>) = named.into_iter().unzip();
+    let names = automaton(&names)?;
+
+    let root = absolute_path(root);
+    for (path, kind) in fs.walk(&root) {
+        if kind != EntryKind::File || !globs.is_match(path.strip_prefix(&root).unwrap_or(&path)) {
+            continue;
+        }
+        let text = match fs.read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+
+        let mut candidates: Vec<usize> = names
+            .find_overlapping_iter(&text)
+            .flat_map(|found| named[found.pattern().as_usize()].iter().copied())
+            .collect();
+        if candidates.is_empty() {
+            continue;
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let file = absolute_path(&path);
+        let moved = moved_path(&moves, &file).map_or_else(|| file.clone(), |(moved, _)| moved);
+        let file_dir = file.parent().unwrap_or(Path::new("/"));
+        let moved_dir = moved.parent().unwrap_or(Path::new("/"));
+
+        let found_replacements: Vec<(usize, Vec<Replacement>)> = candidates
+            .into_iter()
+            .map(|index| (index, replacements(&activities[index], file_dir, moved_dir)))
+            .collect();
+        let references: Vec<&Replacement> = found_replacements
+            .iter()
+            .flat_map(|(_, replacements)| replacements.iter())
+            .collect();
+        let owners: Vec<usize> = found_replacements
+            .iter()
+            .flat_map(|(index, replacements)| replacements.iter().map(|_| *index))
+            .collect();
+        let matcher = matcher(&references)?;
+
+        // changed lines of each activity, with only its own references replaced
+        let mut lines: BTreeMap<usize, Vec<(usize, String, String)>> = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let found = find_references(line, &references, &matcher);
+            let mut owned: Vec<usize> = found.iter().map(|&(_, _, r)| owners[r]).collect();
+            owned.sort_unstable();
+            owned.dedup();
+
+            for index in owned {
+                let own: Vec<(usize, usize, usize)> = found
+                    .iter()
+                    .copied()
+                    .filter(|&(_, _, r)| owners[r] == index)
+                    .collect();
+                let replaced = apply_references(line, &references, &own);
+                lines
+                    .entry(index)
+                    .or_default()
+                    .push((number + 1, line.to_owned(), replaced));
+            }
+        }
+
+        for (index, replacements) in found_replacements {
+            if let Some(lines) = lines.remove(&index) {
+                activities[index].references.push(ReferenceUpdate {
+                    file: moved.clone(),
+                    replacements,
+                    lines,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Rewrite the files holding references to the renamed files, each one once.
+pub fn apply_reference_updates(
+    fs: &dyn FileSystem,
+    updates: &[(usize, &ReferenceUpdate)],
+) -> Result<(), Error> {
+    let mut files: Vec<(&PathBuf, usize, Vec<&Replacement>)> = Vec::new();
+    for (line, update) in updates {
+        match files.iter_mut().find(|(file, _, _)| **file == update.file) {
+            Some((_, _, replacements)) => replacements.extend(update.replacements.iter()),
+            None => files.push((&update.file, *line, update.replacements.iter().collect())),
+        }
+    }
+
+    for (file, line, replacements) in files {
+        let text = fs
+            .read_to_string(file)
+            .map_err(|err| Error::io(line, file, file, err))?;
+        let replaced = replace_references(&text, &replacements)?;
+        if replaced != text {
+            fs.rewrite(file, replaced.as_bytes())
+                .map_err(|err| Error::io(line, file, file, err))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::{find_reference_updates, replace_references, ReferenceKind, Replacement};
+    use crate::config::Config;
+    use crate::exec::{batch_operations, perfom_operations};
+    use crate::filelist::FileList;
+    use crate::filesystem::{absolute_path, FileSystem, RealFileSystem};
+    use crate::memory::MemoryFileSystem;
+
+    #[test]
+    fn test_replace_references() {
+        let name = Replacement {
+            old: "a.txt".to_owned(),
+            new: "b.txt".to_owned(),
+            kind: ReferenceKind::Name,
+        };
+        let path = Replacement {
+            old: "docs/a.txt".to_owned(),
+            new: "notes/a.txt".to_owned(),
+            kind: ReferenceKind::Path,
+        };
+
+        assert_eq!(
+            "see b.txt, data.txt, x/a.txt and a.txt.bak (b.txt).",
+            replace_references(
+                "see a.txt, data.txt, x/a.txt and a.txt.bak (a.txt).",
+                &[&name]
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            "[notes/a.txt](./notes/a.txt) ../docs/a.txt",
+            replace_references("[docs/a.txt](./docs/a.txt) ../docs/a.txt", &[&name, &path])
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_update_references() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let root = tempdir.path();
+        for (name, content) in [
+            ("src/a.rs", "fn a() {}\n".to_owned()),
+            (
+                "docs/index.md",
+                "See [a](../src/a.rs).\nUnrelated.\n".to_owned(),
+            ),
+            (
+                "README.md",
+                format!("`src/a.rs` or {}\n", root.join("src/a.rs").display()),
+            ),
+            ("notes.txt", "src/a.rs\n".to_owned()),
+        ] {
+            fs::create_dir_all(root.join(name).parent().unwrap()).unwrap();
+            fs::write(root.join(name), content).unwrap();
+        }
+
+        let before = FileList::new_from_raw(format!("{}\n", root.join("src/a.rs").display()));
+        let after = FileList::new_from_raw(format!("{}\n", root.join("src/lib/b.rs").display()));
+        let config = Config {
+            mkdir: true,
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        let mut outcome =
+            batch_operations(&RealFileSystem, &before.unwrap(), &after.unwrap(), &config).unwrap();
+        find_reference_updates(&RealFileSystem, root, &["**/*.md".to_owned()], &mut outcome)
+            .unwrap();
+        assert_eq!(2, outcome[0].references.len());
+        perfom_operations(&RealFileSystem, &outcome, &config).unwrap();
+
+        let read = |name: &str| fs::read_to_string(root.join(name)).unwrap();
+        assert_eq!(
+            "See [a](../src/lib/b.rs).\nUnrelated.\n",
+            read("docs/index.md")
+        );
+        assert_eq!(
+            format!(
+                "`src/lib/b.rs` or {}\n",
+                root.join("src/lib/b.rs").display()
+            ),
+            read("README.md")
+        );
+        assert_eq!("src/a.rs\n", read("notes.txt"));
+        assert!(root.join("src/lib/b.rs").exists());
+    }
+
+    #[test]
+    fn test_update_references_in_memory() {
+        let fs = MemoryFileSystem::with_files(["/w/a/README.md", "/w/b/README.md", "/w/c.txt"]);
+        for (name, content) in [
+            ("/w/a/index.md", "README.md and c.txt\n"),
+            ("/w/b/index.md", "README.md and ../c.txt\n"),
+        ] {
+            fs.write(Path::new(name), content.as_bytes());
+        }
+
+        let before = FileList::new_from_raw("/w/a/README.md\n/w/c.txt\n".to_owned()).unwrap();
+        let after = FileList::new_from_raw("/w/a/INDEX.md\n/w/d.txt\n".to_owned()).unwrap();
+        let config = Config {
+            yes: true,
+            quiet: true,
+            reference_root: PathBuf::from("/w"),
+            update_references: vec!["**/*.md".to_owned()],
+            ..Config::new()
+        };
+
+        // only the names next to the renamed file are its own
+        let outcome = batch_operations(&fs, &before, &after, &config).unwrap();
+        perfom_operations(&fs, &outcome, &config).unwrap();
+
+        let read = |name: &str| fs.read_to_string(Path::new(name)).unwrap();
+        assert_eq!("INDEX.md and c.txt\n", read("/w/a/index.md"));
+        assert_eq!("README.md and ../d.txt\n", read("/w/b/index.md"));
+    }
+
+    #[test]
+    fn test_update_references_relative_root() {
+        let here = absolute_path(Path::new("."));
+        let fs = MemoryFileSystem::with_files([here.join("a.txt")]);
+        for name in [here.join("index.md"), PathBuf::from("/elsewhere/index.md")] {
+            fs.write(&name, b"a.txt\n");
+        }
+
+        let raw = |name: &str| format!("{}\n", here.join(name).display());
+        let before = FileList::new_from_raw(raw("a.txt")).unwrap();
+        let after = FileList::new_from_raw(raw("b.txt")).unwrap();
+        let config = Config {
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+
+        // the root is the current dir, not the whole tree
+        let mut outcome = batch_operations(&fs, &before, &after, &config).unwrap();
+        find_reference_updates(&fs, Path::new("."), &["*.md".to_owned()], &mut outcome).unwrap();
+        assert_eq!(1, outcome[0].references.len());
+        assert_eq!(here.join("index.md"), outcome[0].references[0].file);
+    }
+}
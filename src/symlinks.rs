@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::ConflictStrategy;
+use crate::exec::Activity;
+use crate::filesystem::{absolute_path, EntryKind, FileSystem};
+
+/// A symlink to point at the new name of its target once the plan has run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymlinkFix {
+    /// Where the link is after the plan has run.
+    pub link: PathBuf,
+    pub old_target: PathBuf,
+    pub new_target: PathBuf,
+}
+
+/// Where `path` ends up once the renames of `moves` have run, a path below a
+/// renamed dir moves along with it. Also gives the index of the rename.
+pub(crate) fn moved_path(
+    moves: &HashMap<PathBuf, (PathBuf, usize)>,
+    path: &Path,
+) -> Option<(PathBuf, usize)> {
+    path.ancestors().find_map(|ancestor| {
+        let (destination, index) = moves.get(ancestor)?;
+        let rest = path.strip_prefix(ancestor).ok()?;
+
+        if rest.as_os_str().is_empty() {
+            Some((destination.clone(), *index))
+        } else {
+            Some((destination.join(rest), *index))
+        }
+    })
+}
+
+/// Path leading from the `from` dir to `to`, both absolute
+pub fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+/// Attach to the activities the symlinks below `root` pointing at their
+/// source, or inside it for dirs. Entries that cannot be read are left alone.
+pub fn find_symlink_fixes(fs: &dyn FileSystem, root: &Path, activities: &mut [Activity]) {
+    let moves: HashMap<PathBuf, (PathBuf, usize)> = activities
+        .iter()
+        .enumerate()
+        .filter(|(_, activity)| activity.on_conflict != Some(ConflictStrategy::Skip))
+        .filter(|(_, activity)| activity.is_rename())
+        .map(|(index, activity)| {
+            (
+                absolute_path(&activity.source),
+                (absolute_path(&activity.destination), index),
+            )
+        })
+        .collect();
+
+    for (link, kind) in fs.walk(&absolute_path(root)) {
+        if kind != EntryKind::Symlink {
+            continue;
+        }
+
+        let link = link.as_path();
+        let old_target = match fs.read_link(link) {
+            Ok(target) => target,
+            Err(_) => continue,
+        };
+        let link_dir = link.parent().unwrap_or(Path::new("/"));
+
+        let (new_target, index) =
+            match moved_path(&moves, &absolute_path(&link_dir.join(&old_target))) {
+                Some(moved) => moved,
+                None => continue,
+            };
+
+        // the link itself may be renamed by the plan
+        let link = match moved_path(&moves, link) {
+            Some((link, _)) => link,
+            None => link.to_path_buf(),
+        };
+        let new_target = if old_target.is_absolute() {
+            new_target
+        } else {
+            relative_path(link.parent().unwrap_or(Path::new("/")), &new_target)
+        };
+
+        activities[index].symlinks.push(SymlinkFix {
+            link,
+            old_target,
+            new_target,
+        });
+    }
+}
+
+/// Point a symlink at its new target.
+pub fn rewrite_symlink(fs: &dyn FileSystem, fix: &SymlinkFix) -> io::Result<()> {
+    fs.remove_file(&fix.link)?;
+    fs.symlink(&fix.new_target, &fix.link)
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::{Path, PathBuf};
+
+    use super::relative_path;
+    use crate::config::Config;
+    use crate::exec::{batch_operations, perfom_operations};
+    use crate::filelist::FileList;
+    use crate::filesystem::{FileSystem, RealFileSystem};
+    use crate::memory::MemoryFileSystem;
+
+    #[test]
+    fn test_relative_path() {
+        let relative = |from: &str, to: &str| relative_path(Path::new(from), Path::new(to));
+
+        assert_eq!(PathBuf::from("b.txt"), relative("/a", "/a/b.txt"));
+        assert_eq!(PathBuf::from("../c/b.txt"), relative("/a/d", "/a/c/b.txt"));
+        assert_eq!(PathBuf::from("../.."), relative("/a/b/c", "/a"));
+    }
+
+    #[test]
+    fn test_fix_symlinks() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let root = tempdir.path();
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::create_dir_all(root.join("links")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("dir/b.txt"), "b").unwrap();
+        fs::write(root.join("c.txt"), "c").unwrap();
+
+        symlink("../a.txt", root.join("links/relative")).unwrap();
+        symlink(root.join("dir/b.txt"), root.join("links/absolute")).unwrap();
+        symlink("../c.txt", root.join("links/untouched")).unwrap();
+        symlink("a.txt", root.join("moved")).unwrap();
+
+        let lines = |names: &[&str]| -> String {
+            names
+                .iter()
+                .map(|name| format!("{}\n", root.join(name).display()))
+                .collect()
+        };
+        let before = FileList::new_from_raw(lines(&["a.txt", "dir", "moved"])).unwrap();
+        let after = FileList::new_from_raw(lines(&["new/a.txt", "other", "links/moved"])).unwrap();
+
+        let config = Config {
+            fix_symlinks: true,
+            symlink_root: root.to_path_buf(),
+            mkdir: true,
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+        let outcome = batch_operations(&RealFileSystem, &before, &after, &config).unwrap();
+        assert_eq!(3, outcome.iter().map(|a| a.symlinks.len()).sum::<usize>());
+        perfom_operations(&RealFileSystem, &outcome, &config).unwrap();
+
+        let target = |link: &str| fs::read_link(root.join(link)).unwrap();
+        assert_eq!(PathBuf::from("../new/a.txt"), target("links/relative"));
+        assert_eq!(root.join("other/b.txt"), target("links/absolute"));
+        assert_eq!(PathBuf::from("../c.txt"), target("links/untouched"));
+        assert_eq!(PathBuf::from("../new/a.txt"), target("links/moved"));
+        assert_eq!("a", fs::read_to_string(root.join("links/moved")).unwrap());
+        assert_eq!(
+            "b",
+            fs::read_to_string(root.join("links/absolute")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fix_symlinks_in_memory() {
+        let fs = MemoryFileSystem::with_files(["/w/a.txt", "/w/links/.keep"]);
+        fs.symlink(Path::new("../a.txt"), Path::new("/w/links/a"))
+            .unwrap();
+
+        let before = FileList::new_from_raw("/w/a.txt\n".to_owned()).unwrap();
+        let after = FileList::new_from_raw("/w/docs/b.txt\n".to_owned()).unwrap();
+        let config = Config {
+            fix_symlinks: true,
+            symlink_root: PathBuf::from("/w"),
+            mkdir: true,
+            yes: true,
+            quiet: true,
+            ..Config::new()
+        };
+        let outcome = batch_operations(&fs, &before, &after, &config).unwrap();
+        assert_eq!(1, outcome[0].symlinks.len());
+        perfom_operations(&fs, &outcome, &config).unwrap();
+
+        assert_eq!(
+            PathBuf::from("../docs/b.txt"),
+            fs.read_link(Path::new("/w/links/a")).unwrap()
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+
+/// Digits an unpadded `{n}` is read back with, longer numbers such as years
+/// are not taken for counters.
+const MAX_COUNTER_DIGITS: usize = 3;
+
+/// Piece of a naming template such as `{stem}-{n:03}{ext}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    /// `{name}` or `{name:arg}`, the argument is everything after the first `:`
+    Placeholder {
+        name: String,
+        arg: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pub segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parse a template, `{{` and `}}` stand for literal braces.
+    pub fn parse(template: &str) -> Result<Self, anyhow::Error> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => bail!("Unclosed placeholder in template `{}`", template),
+                        }
+                    }
+
+                    let (name, arg) = match placeholder.split_once(':') {
+                        Some((name, arg)) => (name.to_owned(), Some(arg.to_owned())),
+                        None => (placeholder, None),
+                    };
+                    if name.is_empty() {
+                        bail!("Empty placeholder in template `{}`", template);
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder { name, arg });
+                }
+                '}' => bail!("Unmatched `}}` in template `{}`", template),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Names of the placeholders used, in order of appearance.
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Placeholder { name, .. } => Some(name.as_str()),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Fail on any placeholder not in `known`.
+    pub fn check_placeholders(&self, known: &[&str]) -> Result<(), anyhow::Error> {
+        for name in self.placeholders() {
+            if !known.contains(&name) {
+                bail!(
+                    "Unknown placeholder `{{{}}}`, expected one of {}",
+                    name,
+                    known
+                        .iter()
+                        .map(|name| format!("`{{{}}}`", name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Render the template, asking `value` for each placeholder.
+    pub fn render<F>(&self, mut value: F) -> Result<String, anyhow::Error>
+    where
+        F: FnMut(&str, Option<&str>) -> Result<String, anyhow::Error>,
+    {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => output += literal,
+                Segment::Placeholder { name, arg } => output += &value(name, arg.as_deref())?,
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Match `text` against the template, the inverse of `render`.
+    ///
+    /// Placeholders in `fixed` must match their value, `{n}` matches a number
+    /// as `format_number` would write it and any other placeholder matches a
+    /// non-empty string. Returns the captured placeholders.
+    pub fn capture(
+        &self,
+        text: &str,
+        fixed: &HashMap<&str, &str>,
+    ) -> Option<HashMap<String, String>> {
+        let mut captures = HashMap::new();
+        if self.capture_from(0, text, fixed, &mut captures) {
+            return Some(captures);
+        }
+
+        None
+    }
+
+    fn capture_from(
+        &self,
+        index: usize,
+        text: &str,
+        fixed: &HashMap<&str, &str>,
+        captures: &mut HashMap<String, String>,
+    ) -> bool {
+        let segment = match self.segments.get(index) {
+            Some(segment) => segment,
+            None => return text.is_empty(),
+        };
+
+        let (name, arg) = match segment {
+            Segment::Literal(literal) => {
+                return match text.strip_prefix(literal.as_str()) {
+                    Some(rest) => self.capture_from(index + 1, rest, fixed, captures),
+                    None => false,
+                };
+            }
+            Segment::Placeholder { name, arg } => (name.as_str(), arg.as_deref()),
+        };
+
+        if let Some(value) = fixed.get(name) {
+            return match text.strip_prefix(value) {
+                Some(rest) => self.capture_from(index + 1, rest, fixed, captures),
+                None => false,
+            };
+        }
+
+        // try the longest candidate first so that names keep as much as possible
+        let mut ends: Vec<usize> = text.char_indices().map(|(i, _)| i).skip(1).collect();
+        ends.push(text.len());
+        for end in ends.into_iter().rev() {
+            let candidate = &text[..end];
+            if name == "n" && !is_formatted_number(candidate, arg) {
+                continue;
+            }
+
+            captures.insert(name.to_owned(), candidate.to_owned());
+            if self.capture_from(index + 1, &text[end..], fixed, captures) {
+                return true;
+            }
+        }
+        captures.remove(name);
+
+        false
+    }
+}
+
+/// Write a number following a `{n:03}` style argument: the width, zero padded
+/// when it starts with `0`.
+pub fn format_number(number: u64, arg: Option<&str>) -> Result<String, anyhow::Error> {
+    let spec = arg.unwrap_or("");
+    if spec.is_empty() {
+        return Ok(number.to_string());
+    }
+
+    let width: usize = match spec.parse() {
+        Ok(width) => width,
+        Err(_) => bail!(
+            "Invalid number format `{}`, expected a width like `03`",
+            spec
+        ),
+    };
+
+    if spec.starts_with('0') {
+        Ok(format!("{:0width$}", number, width = width))
+    } else {
+        Ok(format!("{:width$}", number, width = width))
+    }
+}
+
+/// Wether `text` could have been written by `format_number` with `arg`, as a
+/// counter: no longer than the width of the format, or three digits when
+/// unpadded.
+fn is_formatted_number(text: &str, arg: Option<&str>) -> bool {
+    let digits = text.trim_start_matches(' ');
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+
+    let width = match arg.unwrap_or("").parse() {
+        Ok(width) => width,
+        Err(_) => MAX_COUNTER_DIGITS,
+    };
+    if text.len() > width {
+        return false;
+    }
+
+    match digits.parse::<u64>() {
+        Ok(number) => format_number(number, arg).is_ok_and(|formatted| formatted == text),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{format_number, Segment, Template};
+
+    #[test]
+    fn test_parse() {
+        let template = Template::parse("{{{stem}}}-{n:03}{ext}").unwrap();
+
+        assert_eq!(
+            vec![
+                Segment::Literal("{".to_owned()),
+                Segment::Placeholder {
+                    name: "stem".to_owned(),
+                    arg: None
+                },
+                Segment::Literal("}-".to_owned()),
+                Segment::Placeholder {
+                    name: "n".to_owned(),
+                    arg: Some("03".to_owned())
+                },
+                Segment::Placeholder {
+                    name: "ext".to_owned(),
+                    arg: None
+                },
+            ],
+            template.segments
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Template::parse("{stem").is_err());
+        assert!(Template::parse("stem}").is_err());
+        assert!(Template::parse("{}").is_err());
+        assert!(Template::parse("{name}")
+            .unwrap()
+            .check_placeholders(&["stem", "n", "ext"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse("{stem} ({n:02}){ext}").unwrap();
+        let output = template
+            .render(|name, arg| match name {
+                "stem" => Ok("report".to_owned()),
+                "n" => format_number(7, arg),
+                _ => Ok(".txt".to_owned()),
+            })
+            .unwrap();
+
+        assert_eq!("report (07).txt", output);
+    }
+
+    #[test]
+    fn test_capture() {
+        let fixed = HashMap::from([("ext", ".tar.gz")]);
+        let template = Template::parse("{stem}-{n:03}{ext}").unwrap();
+        let captures = template.capture("backup-2024-012.tar.gz", &fixed).unwrap();
+
+        assert_eq!("backup-2024", captures["stem"]);
+        assert_eq!("012", captures["n"]);
+        assert!(template.capture("backup-12.tar.gz", &fixed).is_none());
+        assert!(template.capture("backup-2024.tar.gz", &fixed).is_none());
+
+        let fixed = HashMap::from([("ext", ".txt")]);
+        let template = Template::parse("{stem}_{n}{ext}").unwrap();
+        assert_eq!("7", template.capture("file_7.txt", &fixed).unwrap()["n"]);
+        assert!(template.capture("report_2024.txt", &fixed).is_none());
+    }
+}
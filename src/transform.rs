@@ -0,0 +1,219 @@
+use std::path::{is_separator, Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::errors::Error;
+use crate::filesystem::{get_last_component, split_extension};
+
+/// Rewrite of file names applied to the buffer, see `--transform`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transform {
+    /// `my file.TXT` -> `my file.txt`
+    Lower,
+    /// `my file.txt` -> `MY FILE.TXT`
+    Upper,
+    /// `my file.txt` -> `My File.txt`
+    Title,
+    /// `My File.txt` -> `my_file.txt`
+    Snake,
+    /// `My File.txt` -> `my-file.txt`
+    Kebab,
+    /// `Crème brûlée.txt` -> `Creme brulee.txt`
+    Ascii,
+    /// `Crème Brûlée!.TXT` -> `creme-brulee.txt`
+    Slug,
+    /// `my   file .txt` -> `my file.txt`
+    Collapse,
+}
+
+impl Transform {
+    /// Apply the transform to a file name, the extension only changes case
+    /// or is transliterated.
+    pub fn apply(&self, file_name: &str) -> String {
+        // hidden files keep their leading dots
+        let name = file_name.trim_start_matches('.');
+        let dots = &file_name[..file_name.len() - name.len()];
+        let (stem, ext) = split_extension(name);
+
+        let new_stem = match self {
+            Transform::Lower => stem.to_lowercase(),
+            Transform::Upper => stem.to_uppercase(),
+            Transform::Title => title_case(stem),
+            Transform::Snake => words(stem).join("_"),
+            Transform::Kebab => words(stem).join("-"),
+            Transform::Ascii => deunicode::deunicode(stem),
+            Transform::Slug => slug(stem),
+            Transform::Collapse => stem.split_whitespace().collect::<Vec<_>>().join(" "),
+        };
+        // nothing left of the name, refused by `transform_path`
+        if new_stem.is_empty() && !stem.is_empty() {
+            return String::new();
+        }
+        let ext = match self {
+            Transform::Lower | Transform::Slug => ext.to_lowercase(),
+            Transform::Upper => ext.to_uppercase(),
+            Transform::Ascii => deunicode::deunicode(ext),
+            _ => ext.to_owned(),
+        };
+
+        format!("{}{}{}", dots, new_stem, ext)
+    }
+}
+
+/// Apply every transform in order to the last component of `path`. Path
+/// separators produced by transliteration, as in `1/2` for `½`, become `-`.
+pub fn transform_path(path: &Path, transforms: &[Transform]) -> Result<PathBuf, Error> {
+    let file_name = get_last_component(path);
+    if file_name.is_empty() || file_name == "." || file_name == ".." {
+        return Ok(path.to_path_buf());
+    }
+
+    let mut renamed = file_name.to_owned();
+    for transform in transforms {
+        renamed = transform.apply(&renamed).replace(is_separator, "-");
+        if renamed.is_empty() || renamed == "." || renamed == ".." {
+            return Err(Error::EmptyName {
+                path: path.to_path_buf(),
+            });
+        }
+    }
+
+    Ok(path.with_file_name(renamed))
+}
+
+/// Lowercase words of a name, split on spaces, `-`, `_` and camelCase humps.
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut previous_lower = false;
+    for c in text.chars() {
+        if c.is_whitespace() || c == '-' || c == '_' {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            previous_lower = false;
+            continue;
+        }
+
+        if c.is_uppercase() && previous_lower {
+            words.push(std::mem::take(&mut word));
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+fn title_case(text: &str) -> String {
+    let mut output = String::new();
+    let mut start = true;
+    for c in text.chars() {
+        if start {
+            output.extend(c.to_uppercase());
+        } else {
+            output.extend(c.to_lowercase());
+        }
+        start = !c.is_alphanumeric() && c != '\'';
+    }
+
+    output
+}
+
+fn slug(text: &str) -> String {
+    let mut output = String::new();
+    for c in deunicode::deunicode(text).chars() {
+        if c.is_ascii_alphanumeric() {
+            output.push(c.to_ascii_lowercase());
+        } else if !output.is_empty() && !output.ends_with('-') {
+            output.push('-');
+        }
+    }
+
+    output.trim_end_matches('-').to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use super::{transform_path, Transform};
+    use crate::errors::Error;
+
+    #[test]
+    fn test_transforms() {
+        let cases = [
+            (Transform::Lower, "My File.TXT", "my file.txt"),
+            (Transform::Upper, "my file.txt", "MY FILE.TXT"),
+            (Transform::Title, "my file's name.txt", "My File's Name.txt"),
+            (
+                Transform::Snake,
+                "My FileName-v2.txt",
+                "my_file_name_v2.txt",
+            ),
+            (Transform::Kebab, "my_file  name.txt", "my-file-name.txt"),
+            (Transform::Ascii, "Crème brûlée.txt", "Creme brulee.txt"),
+            (Transform::Slug, "Crème Brûlée!.TXT", "creme-brulee.txt"),
+            (Transform::Slug, ".Hidden File", ".hidden-file"),
+            (Transform::Collapse, " my   file .txt", "my file.txt"),
+            (Transform::Lower, "Archive.TAR.GZ", "archive.tar.gz"),
+        ];
+
+        for (transform, name, expected) in cases {
+            assert_eq!(
+                expected,
+                transform.apply(name),
+                "{:?} {:?}",
+                transform,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_transform_path() {
+        assert_eq!(
+            PathBuf::from("My Photos/img_0001.jpg"),
+            transform_path(
+                Path::new("My Photos/IMG 0001.JPG"),
+                &[Transform::Lower, Transform::Snake]
+            )
+            .unwrap()
+        );
+
+        // transliterated separators do not move the file
+        assert_eq!(
+            PathBuf::from("docs/1-2 cup.txt"),
+            transform_path(Path::new("docs/½ cup.txt"), &[Transform::Ascii]).unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("a-b.txt"),
+            transform_path(Path::new("a／b.txt"), &[Transform::Ascii]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_empty_names_are_refused() {
+        for (name, transform) in [
+            ("docs/!!!", Transform::Slug),
+            ("docs/!!!.txt", Transform::Slug),
+            ("___", Transform::Snake),
+            ("--", Transform::Kebab),
+        ] {
+            assert!(
+                matches!(
+                    transform_path(Path::new(name), &[transform]),
+                    Err(Error::EmptyName { .. })
+                ),
+                "{:?} {:?}",
+                transform,
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::filesystem::absolute_path;
+
+/// Home trash of the freedesktop.org trash spec, `$XDG_DATA_HOME/Trash`.
+pub fn home_trash() -> PathBuf {
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local").join("share"),
+            None => env::temp_dir(),
+        },
+    };
+
+    data_home.join("Trash")
+}
+
+/// Percent-encode a path for the `Path=` key of a `.trashinfo` file
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path_bytes(path).iter() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded += &format!("%{:02X}", byte),
+        }
+    }
+
+    encoded
+}
+
+/// Raw bytes of the path, so that non UTF-8 names can be restored
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().into()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    path.to_string_lossy().into_owned().into_bytes().into()
+}
+
+/// Move `path` into the `files` dir of `trash`, next to its `.trashinfo`.
+/// The original path is written relative to `topdir` for per-mount trashes.
+pub fn move_to_trash(trash: &Path, topdir: Option<&Path>, path: &Path) -> io::Result<PathBuf> {
+    let path = absolute_path(path);
+    let (files, info) = (trash.join("files"), trash.join("info"));
+    for dir in [&files, &info] {
+        create_private_dir(dir)?;
+    }
+
+    let original = match topdir.and_then(|topdir| path.strip_prefix(topdir).ok()) {
+        Some(relative) => relative.to_path_buf(),
+        None => path.clone(),
+    };
+    let content = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(&original),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "nothing to trash"))?
+        .to_string_lossy()
+        .into_owned();
+
+    // the info file reserves the name, as the spec asks
+    let mut number = 1;
+    loop {
+        let trashed_name = match number {
+            1 => name.clone(),
+            _ => format!("{}.{}", name, number),
+        };
+        let info_path = info.join(format!("{}.trashinfo", trashed_name));
+
+        let mut info_file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                number += 1;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        let trashed = files.join(&trashed_name);
+        let moved = info_file.write_all(content.as_bytes()).and_then(|_| {
+            match trashed.symlink_metadata() {
+                Ok(_) => Err(io::ErrorKind::AlreadyExists.into()),
+                Err(_) => fs::rename(&path, &trashed),
+            }
+        });
+        match moved {
+            Ok(()) => return Ok(trashed),
+            Err(err) => {
+                drop(fs::remove_file(&info_path));
+                if err.kind() != io::ErrorKind::AlreadyExists {
+                    return Err(err);
+                }
+                number += 1;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+/// Move `path` to the trash: the home one when it is on the same filesystem,
+/// otherwise `.Trash-$UID` at the top of the mount holding it.
+#[cfg(unix)]
+pub fn trash(path: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = absolute_path(path);
+    let device = path.symlink_metadata()?.dev();
+
+    let home = home_trash();
+    let home_device = home
+        .ancestors()
+        .find_map(|dir| dir.metadata().ok())
+        .map(|metadata| metadata.dev());
+    if home_device == Some(device) {
+        return move_to_trash(&home, None, &path);
+    }
+
+    // the top dir is the last ancestor on the same device
+    let mut topdir = path.parent().unwrap_or(Path::new("/"));
+    while let Some(parent) = topdir.parent() {
+        match parent.metadata() {
+            Ok(metadata) if metadata.dev() == device => topdir = parent,
+            _ => break,
+        }
+    }
+
+    let trash = topdir.join(format!(".Trash-{}", uzers::get_current_uid()));
+    move_to_trash(&trash, Some(topdir), &path)
+}
+
+#[cfg(not(unix))]
+pub fn trash(path: &Path) -> io::Result<PathBuf> {
+    move_to_trash(&home_trash(), None, path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{encode_path, move_to_trash};
+
+    #[test]
+    fn test_encode_path() {
+        assert_eq!(
+            "/home/a%20b/%C3%A9.txt",
+            encode_path(Path::new("/home/a b/é.txt"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_encode_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(
+            "/home/%FF.txt",
+            encode_path(Path::new(OsStr::from_bytes(b"/home/\xff.txt")))
+        );
+    }
+
+    #[test]
+    fn test_move_to_trash() {
+        let tempdir = tempfile::tempdir().expect("Error creating temp directory");
+        let root = tempdir.path();
+        let trash = root.join("Trash");
+        for content in ["first", "second"] {
+            fs::write(root.join("a.txt"), content).unwrap();
+            move_to_trash(&trash, None, &root.join("a.txt")).unwrap();
+        }
+
+        assert!(!root.join("a.txt").exists());
+        assert_eq!(
+            "first",
+            fs::read_to_string(trash.join("files/a.txt")).unwrap()
+        );
+        assert_eq!(
+            "second",
+            fs::read_to_string(trash.join("files/a.txt.2")).unwrap()
+        );
+
+        let info = fs::read_to_string(trash.join("info/a.txt.trashinfo")).unwrap();
+        assert!(info.starts_with(&format!(
+            "[Trash Info]\nPath={}\nDeletionDate=",
+            encode_path(&root.join("a.txt"))
+        )));
+
+        fs::create_dir(root.join("dir")).unwrap();
+        move_to_trash(&trash, Some(root), &root.join("dir")).unwrap();
+        let info = fs::read_to_string(trash.join("info/dir.trashinfo")).unwrap();
+        assert!(info.contains("\nPath=dir\n"));
+    }
+}
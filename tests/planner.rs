@@ -0,0 +1,145 @@
+//! Random trees renamed through random edited buffers, checked against the
+//! in-memory filesystem.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+
+use rnr_buf::{Config, ConflictStrategy, MemoryFileSystem};
+
+/// Names are never dirs and dirs never files, so that a destination cannot
+/// land inside a file.
+fn name_pool() -> Vec<String> {
+    let mut pool = Vec::new();
+    for dir in ["", "d0/", "d1/", "d1/d2/"] {
+        for name in ["f0", "f1", "f2", "f3", "f4.txt", "f5.txt"] {
+            pool.push(format!("{}{}", dir, name));
+        }
+    }
+
+    pool
+}
+
+#[derive(Debug, Clone)]
+struct Case {
+    /// Files of the tree, content is the path.
+    tree: Vec<String>,
+    /// Files listed in the buffer, some of the tree.
+    listed: Vec<String>,
+    /// Edited line for every listed file.
+    edited: Vec<String>,
+}
+
+fn case() -> impl Strategy<Value = Case> {
+    let pool = name_pool();
+    let size = pool.len();
+
+    subsequence(pool.clone(), 1..size)
+        .prop_flat_map(|tree| {
+            let count = tree.len();
+            (
+                Just(tree),
+                subsequence((0..count).collect::<Vec<_>>(), 1..=count),
+            )
+        })
+        .prop_flat_map(move |(tree, listed)| {
+            let listed: Vec<String> = listed.into_iter().map(|i| tree[i].clone()).collect();
+            // most lines keep or swap names of the tree, some move elsewhere
+            let edited = listed
+                .iter()
+                .map(|source| {
+                    prop_oneof![
+                        2 => Just(source.clone()),
+                        2 => proptest::sample::select(tree.clone()),
+                        1 => proptest::sample::select(name_pool()),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            (Just(tree), Just(listed), edited)
+        })
+        .prop_map(|(tree, listed, edited)| Case {
+            tree,
+            listed,
+            edited,
+        })
+        .prop_filter("the pool is large enough", move |case| {
+            case.tree.len() < size
+        })
+}
+
+fn buffer(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+fn contents(fs: &MemoryFileSystem) -> BTreeMap<PathBuf, String> {
+    fs.files()
+        .into_iter()
+        .map(|(path, content)| (path, String::from_utf8(content).unwrap()))
+        .collect()
+}
+
+fn run(
+    case: &Case,
+    on_conflict: ConflictStrategy,
+) -> (MemoryFileSystem, Result<(), rnr_buf::Error>) {
+    let fs = MemoryFileSystem::with_files(&case.tree);
+    let config = Config {
+        on_conflict,
+        yes: true,
+        quiet: true,
+        ..Config::new()
+    };
+
+    let original = rnr_buf::parse_buffer(&buffer(&case.listed)).unwrap();
+    let result = rnr_buf::parse_buffer(&buffer(&case.edited))
+        .and_then(|modified| rnr_buf::plan_with(&fs, &original, &modified, &config))
+        .and_then(|activities| rnr_buf::execute_with(&fs, &activities, &config));
+
+    (fs, result)
+}
+
+proptest! {
+    #[test]
+    fn plan_matches_buffer(case in case()) {
+        let before: BTreeMap<PathBuf, String> =
+            case.tree.iter().map(|path| (PathBuf::from(path), path.clone())).collect();
+        let (fs, result) = run(&case, ConflictStrategy::Fail);
+        let after = contents(&fs);
+
+        if result.is_err() {
+            // a rejected plan leaves the tree alone
+            prop_assert_eq!(before, after);
+            return Ok(());
+        }
+
+        let mut expected = before.clone();
+        for source in &case.listed {
+            expected.remove(&PathBuf::from(source));
+        }
+        for (source, destination) in case.listed.iter().zip(&case.edited) {
+            let previous = expected.insert(PathBuf::from(destination), source.clone());
+            prop_assert!(previous.is_none(), "{:?} overwritten", destination);
+        }
+        prop_assert_eq!(expected, after);
+    }
+
+    #[test]
+    fn conflicts_lose_nothing(
+        case in case(),
+        on_conflict in prop_oneof![
+            Just(ConflictStrategy::Skip),
+            Just(ConflictStrategy::Backup),
+            Just(ConflictStrategy::Rename),
+        ],
+    ) {
+        let (fs, result) = run(&case, on_conflict);
+        let after = contents(&fs);
+
+        let before: BTreeSet<String> = case.tree.iter().cloned().collect();
+        let kept: BTreeSet<String> = after.values().cloned().collect();
+        prop_assert_eq!(case.tree.len(), after.len(), "{:?}", result);
+        prop_assert_eq!(before, kept);
+    }
+}